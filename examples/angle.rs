@@ -11,18 +11,19 @@ fn main() -> anyhow::Result<()> {
     let peripherals = Peripherals::take().unwrap();
 
     let mut m5 = M5Go::new(peripherals)?;
+    let mut port_b = m5.port_b.take().expect("Port B is set up by default");
+    let mut leds = m5.leds.take().expect("The M5Go has led bars");
 
     let mut last_read = 0;
 
-    m5.leds.off();
+    leds.off()?;
 
     loop {
-        let read = m5.port_b.read()?;
+        let read = port_b.read()?;
         if read.abs_diff(last_read) > 10 {
             last_read = read;
-            m5.leds
-                .fill(WHITE.with_brightness(brightness_from_read(read)));
-            m5.leds.display();
+            leds.fill(WHITE.with_brightness(brightness_from_read(read)));
+            leds.display()?;
         }
         FreeRtos::delay_ms(50);
     }
@@ -0,0 +1,47 @@
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use m5_go::{battery::ShutdownTime, M5Go};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    // Do not shut down when the load is light, e.g. while the screen is off
+    let mut battery = m5.battery().expect("Port A is set up by default");
+    battery.set_boost_keep_on(true)?;
+    battery.set_auto_power_off(ShutdownTime::Seconds64)?;
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+
+    loop {
+        let mut battery = m5.battery().expect("Port A is set up by default");
+        let level = battery.battery_level()?;
+        let status = if battery.is_full()? {
+            "full"
+        } else if battery.is_charging()? {
+            "charging"
+        } else {
+            "on battery"
+        };
+
+        screen.fill_background(Rgb565::BLACK)?;
+        screen.draw_text(
+            format!("Battery : {}% ({})", level, status).as_str(),
+            Point::new(0, 15),
+            Alignment::Left,
+            Rgb565::WHITE,
+            &FONT_10X20,
+        )?;
+
+        FreeRtos::delay_ms(5000);
+    }
+}
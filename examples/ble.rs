@@ -11,13 +11,14 @@ fn main() -> anyhow::Result<()> {
     let config = BleConfig::new()
         .on_receive(|str| Some(format!("Received: {}", String::from_utf8_lossy(str))));
 
-    m5.setup_ble(config);
+    m5.setup_ble(config)?;
 
-    let ble = m5.ble.unwrap();
+    let ble = m5.ble.take().unwrap();
 
     ble.start()?;
 
-    println!("mac : {}", m5.mac);
+    println!("mac : {}, bluetooth : {}", m5.mac, m5.bt_mac);
+    println!("device id : {}", m5.device_id());
 
     loop {
         if m5.button_a.is_low() {
@@ -0,0 +1,59 @@
+use std::{ops::ControlFlow, time::Duration};
+
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    buttons::ButtonConfig,
+    event::{Event, EventLoopConfig},
+    power::Button,
+    M5Go,
+};
+use smart_leds::colors::{BLUE, GREEN, RED};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    // B repeats while held, to scroll through the colors
+    m5.buttons.set_config(
+        ButtonConfig::new()
+            .long_press(Duration::from_secs(1))
+            .repeat(Duration::from_millis(500), Duration::from_millis(150)),
+    );
+
+    let colors = [RED, GREEN, BLUE];
+    let mut index = 0;
+    let mut lit = false;
+
+    m5.run(&EventLoopConfig::new(), |m5, event| {
+        match event {
+            Event::ButtonClick(Button::A) => lit = !lit,
+            Event::ButtonClick(Button::B) | Event::ButtonRepeat(Button::B) => {
+                index = (index + 1) % colors.len()
+            }
+            Event::ButtonDoubleClick(button) => println!("Double click on {:?}", button),
+            Event::ButtonLongPress(Button::C, held) => {
+                println!("C held for {:?}, leaving", held);
+                return ControlFlow::Break(());
+            }
+            _ => return ControlFlow::Continue(()),
+        }
+
+        if let Some(leds) = &mut m5.leds {
+            let result = if lit {
+                leds.fill(colors[index]);
+                leds.display()
+            } else {
+                leds.off()
+            };
+            if let Err(e) = result {
+                println!("Unable to display the leds: {}", e);
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
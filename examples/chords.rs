@@ -0,0 +1,54 @@
+use std::{ops::ControlFlow, time::Duration};
+
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    buttons::Chord,
+    event::{Event, EventLoopConfig},
+    power::Button,
+    settings::keys,
+    M5Go,
+};
+
+const SETTINGS_VERSION: u16 = 1;
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    m5.buttons.add_chord(
+        "factory reset",
+        Chord::new(&[Button::B])
+            .hold(Duration::from_secs(3))
+            .at_boot(),
+    );
+    m5.buttons
+        .add_chord("settings", Chord::new(&[Button::A, Button::C]));
+    m5.buttons.add_chord(
+        "pairing",
+        Chord::new(&[Button::A, Button::B, Button::C]).hold(Duration::from_secs(2)),
+    );
+
+    // Hold B while powering on to forget the settings
+    if m5.check_boot_chords() == Some("factory reset") {
+        let mut settings = m5.settings(SETTINGS_VERSION, &[])?;
+        settings.remove(keys::LED_BRIGHTNESS)?;
+        settings.remove(keys::VOLUME)?;
+        println!("Settings reset");
+    }
+
+    m5.run(&EventLoopConfig::new(), |_, event| {
+        match event {
+            Event::ButtonChord("settings") => println!("Entering the settings menu"),
+            Event::ButtonChord("pairing") => println!("Entering BLE pairing mode"),
+            Event::ButtonClick(button) => println!("Click on {:?}", button),
+            Event::ButtonLongPress(Button::C, _) => return ControlFlow::Break(()),
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::{ops::ControlFlow, time::Duration};
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor, Size},
+    primitives::Rectangle,
+    text::Alignment,
+};
+use esp_idf_hal::prelude::Peripherals;
+use log::{info, warn};
+use m5_go::{
+    console::{ConsoleConfig, LogConsole},
+    event::{Event, EventLoopConfig},
+    M5Go,
+};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    // The console takes the screen below the title
+    let console = LogConsole::new(
+        ConsoleConfig::new().region(Rectangle::new(Point::new(0, 30), Size::new(320, 210))),
+    );
+    console.install()?;
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+    screen.fill_background(Rgb565::BLACK)?;
+    screen.draw_text(
+        format!("M5Go-{}", m5.device_id()).as_str(),
+        Point::new(0, 20),
+        Alignment::Left,
+        Rgb565::WHITE,
+        &FONT_10X20,
+    )?;
+
+    info!("Started, MAC {}", m5.mac);
+
+    let config = EventLoopConfig::new().tick(Duration::from_millis(200));
+
+    m5.run(&config, |m5, event| {
+        match event {
+            Event::Tick => {
+                if let Err(e) = console.render(&mut screen) {
+                    println!("Unable to draw the console: {}", e);
+                }
+            }
+            Event::ButtonReleased(button) => warn!("Button {:?} released", button),
+            event => info!("{:?}", event),
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::ops::ControlFlow;
+
+use esp_idf_hal::prelude::Peripherals;
+use log::{info, warn};
+use m5_go::{
+    ble::BleConfig,
+    console::{ConsoleConfig, LogConsole},
+    crash::{CrashReport, CLEAR_COMMAND, UART_COMMAND},
+    event::{Event, EventLoopConfig},
+    power::Button,
+    EspPlatform, M5Go,
+};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    // The console keeps the last log lines for the crash report
+    let console = LogConsole::new(ConsoleConfig::new());
+    console.install()?;
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?.with_panic_hook();
+
+    // Kept until it is read, in case the device reboots first
+    let mut report = m5.crash_report()?;
+    match &report {
+        Some(report) => warn!("The previous run crashed:\n{}", report),
+        None => info!("Clean start"),
+    }
+
+    let mut config = BleConfig::new();
+    if let Some(report) = &report {
+        config = config.crash_report(report);
+    }
+    m5.setup_ble(config)?;
+    if let Some(ble) = &m5.ble {
+        ble.start()?;
+    }
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+    console.render(&mut screen)?;
+
+    m5.run(&EventLoopConfig::new(), |m5, event| {
+        match event {
+            Event::UartLine(line) if line == UART_COMMAND => {
+                if let (Some(report), Some(port_c)) = (&report, &mut m5.port_c) {
+                    if let Err(e) = report.write_to(port_c) {
+                        warn!("Unable to send the crash report: {}", e);
+                    }
+                }
+            }
+            Event::CrashReportRead => clear(m5, &mut report),
+            Event::UartLine(line) if line == CLEAR_COMMAND => clear(m5, &mut report),
+            Event::BleWrite(value) if value == CLEAR_COMMAND.as_bytes() => clear(m5, &mut report),
+            // Button A crashes the device to try it out
+            Event::ButtonPressed(Button::A) => panic!("Crash asked with button A"),
+            event => info!("{:?}", event),
+        }
+        if let Err(e) = console.render(&mut screen) {
+            println!("Unable to draw the console: {}", e);
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
+
+fn clear(m5: &mut M5Go<EspPlatform>, report: &mut Option<CrashReport>) {
+    match m5.clear_crash_report() {
+        Ok(()) => {
+            info!("Crash report cleared");
+            *report = None;
+        }
+        Err(e) => warn!("Unable to clear the crash report: {}", e),
+    }
+}
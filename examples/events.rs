@@ -0,0 +1,69 @@
+use std::{ops::ControlFlow, time::Duration};
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    ble::BleConfig,
+    event::{Event, EventLoopConfig},
+    power::Button,
+    M5Go,
+};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    m5.setup_ble(BleConfig::new())?;
+    if let Some(ble) = &m5.ble {
+        ble.start()?;
+    }
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+
+    let config = EventLoopConfig::new()
+        .tick(Duration::from_secs(1))
+        .adc_threshold(2048);
+
+    let mut ticks = 0;
+
+    m5.run(&config, |m5, event| {
+        let text = match event {
+            // Button C leaves the loop
+            Event::ButtonPressed(Button::C) => return ControlFlow::Break(()),
+            Event::Tick => {
+                ticks += 1;
+                format!("{} s", ticks)
+            }
+            event => format!("{:?}", event),
+        };
+
+        println!("{}", text);
+        let drawn = screen.fill_background(Rgb565::BLACK).and_then(|_| {
+            screen.draw_text(
+                text.as_str(),
+                Point::new(0, 15),
+                Alignment::Left,
+                Rgb565::WHITE,
+                &FONT_10X20,
+            )
+        });
+        if let Err(e) = drawn {
+            println!("Unable to draw the event: {}", e);
+        }
+
+        ControlFlow::Continue(())
+    })?;
+
+    screen.turn_off()?;
+
+    Ok(())
+}
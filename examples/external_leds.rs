@@ -0,0 +1,40 @@
+use esp_idf_hal::{delay::FreeRtos, gpio::Pin, prelude::Peripherals};
+use m5_go::{
+    leds::{LedConfig, Leds},
+    M5GoBuilder,
+};
+use smart_leds::colors::{BLUE, ORANGE};
+
+/// A NeoPixel strip plugged on Port B
+const STRIP_LENGTH: usize = 30;
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    // Port B is skipped to free GPIO26 for the strip
+    let (mut m5, unclaimed) = M5GoBuilder::new(peripherals).without_port_b().build()?;
+    let (data_pin, _, _) = unclaimed.port_b.expect("Port B was skipped");
+
+    // The side bars use RMT channel 0
+    let mut strip = Leds::new(
+        &LedConfig::new()
+            .pin(data_pin.pin())
+            .channel(1)
+            .count(STRIP_LENGTH),
+    )?;
+    let mut bars = m5.leds.take().expect("The M5Go has led bars");
+
+    bars.fill(BLUE);
+    bars.display()?;
+
+    loop {
+        for index in 0..strip.len() {
+            strip.fill(Default::default());
+            strip.set_color_at_index(index, ORANGE)?;
+            strip.display()?;
+            FreeRtos::delay_ms(50);
+        }
+    }
+}
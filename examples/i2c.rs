@@ -18,27 +18,26 @@ fn main() -> anyhow::Result<()> {
     let peripherals = Peripherals::take().unwrap();
 
     let mut m5 = m5_go::M5Go::new(peripherals)?;
+    let mut port_a = m5.port_a.take().expect("Port A is set up by default");
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
 
-    m5.screen.turn_on();
-    m5.screen.fill_background(Rgb565::BLACK);
+    screen.turn_on()?;
+    screen.fill_background(Rgb565::BLACK)?;
 
     let mut last_c = 0_f32;
     let mut last_f = 0_f32;
     let mut last_h = 0_f32;
 
     // Init sensor : will send a message every 0.5 seconds
-    m5.port_a
-        .write(ADDRESS, &[0x20, 0x32], 100)
-        .ok()
-        .or_else(|| {
-            println!("Write failed");
-            None
-        });
+    port_a.write(ADDRESS, &[0x20, 0x32], 100).ok().or_else(|| {
+        println!("Write failed");
+        None
+    });
 
     loop {
         let mut buffer = [0; 6];
 
-        if m5.port_a.read(ADDRESS, &mut buffer, 100).is_ok() {
+        if port_a.read(ADDRESS, &mut buffer, 100).is_ok() {
             println!("Read: {:?}", buffer);
             let data = buffer
                 .to_vec()
@@ -50,38 +49,38 @@ fn main() -> anyhow::Result<()> {
             let h = (((data[3] * 256.0) + data[4]) * 100.) / 65535.0;
 
             if c != last_c || f != last_f || h != last_h {
-                m5.screen.fill_background(Rgb565::BLACK);
+                screen.fill_background(Rgb565::BLACK)?;
                 last_c = c;
                 last_f = f;
                 last_h = h;
-                m5.screen.draw_text(
+                screen.draw_text(
                     format!("Temperature : {:.2}C", c).as_str(),
-                    Point::new(m5.screen.driver.width() as i32 / 2, 20),
+                    Point::new(screen.width() as i32 / 2, 20),
                     Alignment::Center,
                     Rgb565::WHITE,
                     &FONT_10X20,
-                );
-                m5.screen.draw_text(
+                )?;
+                screen.draw_text(
                     format!("Temperature : {:.2}F", f).as_str(),
-                    Point::new(m5.screen.driver.width() as i32 / 2, 60),
+                    Point::new(screen.width() as i32 / 2, 60),
                     Alignment::Center,
                     Rgb565::WHITE,
                     &FONT_10X20,
-                );
-                m5.screen.draw_text(
+                )?;
+                screen.draw_text(
                     format!("Relative Humidity : {:.2}", h).as_str(),
-                    Point::new(m5.screen.driver.width() as i32 / 2, 100),
+                    Point::new(screen.width() as i32 / 2, 100),
                     Alignment::Center,
                     Rgb565::WHITE,
                     &FONT_10X20,
-                );
+                )?;
             }
         } else {
             println!("Read failed");
         }
         if m5.button_a.is_low() {
             println!("send");
-            m5.port_a
+            port_a
                 .write(ADDRESS, "Hello!".as_bytes(), 100)
                 .expect("Write failed");
         }
@@ -1,24 +1,73 @@
-use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use std::{ops::ControlFlow, time::Duration};
+
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    animation::{
+        AnimationConfig, Breathing, Comet, Effect, EffectExt, Fire, Palette, Rainbow, Segments,
+        Sparkle, TheaterChase,
+    },
+    event::{Event, EventLoopConfig},
+    power::Button,
+    M5Go,
+};
 use smart_leds::colors::{BROWN, CYAN, LIME_GREEN, MAGENTA, PINK, YELLOW};
 
+const EFFECTS: usize = 7;
+
+fn effect(index: usize) -> Box<dyn Effect> {
+    let colors = Palette::new(&[MAGENTA, YELLOW, BROWN, LIME_GREEN, CYAN, PINK]);
+    match index {
+        // The chase this example used to draw by hand
+        0 => Box::new(Comet::new().palette(colors).tail(1)),
+        1 => Box::new(Rainbow::new()),
+        2 => Box::new(Breathing::new().palette(colors)),
+        3 => Box::new(Fire::new()),
+        4 => Box::new(Sparkle::new().palette(colors)),
+        5 => Box::new(TheaterChase::new().palette(colors)),
+        // A fire on each side bar, then a white flash
+        _ => Box::new(
+            Segments::new()
+                .part(0..5, Fire::new().seed(1))
+                .part(5..10, Fire::new().seed(2))
+                .for_duration(Duration::from_secs(5))
+                .then(Breathing::new().period(Duration::from_millis(500)))
+                .for_duration(Duration::from_millis(5500)),
+        ),
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     esp_idf_sys::link_patches();
 
     let peripherals = Peripherals::take().unwrap();
 
-    let mut m5 = m5_go::M5Go::new(peripherals)?;
+    let mut m5 = M5Go::new(peripherals)?;
 
-    let colors = [MAGENTA, YELLOW, BROWN, LIME_GREEN, CYAN, PINK];
+    // Button A shows the next effect, the animations run from a timer meanwhile
+    let mut index = 0;
+    let mut animation = Some(m5.animate(effect(index), &AnimationConfig::new())?);
 
-    loop {
-        for color in colors {
-            for index in 0..10 {
-                m5.leds.off();
-                m5.leds.set_color_at_index(index, color);
-                m5.leds.display();
-                FreeRtos::delay_ms(100);
+    m5.run(&EventLoopConfig::new(), |m5, event| {
+        match event {
+            Event::ButtonClick(Button::A) => index = (index + 1) % EFFECTS,
+            Event::AnimationFinished(name) => println!("The {} is over", name),
+            Event::ButtonLongPress(Button::C, _) => return ControlFlow::Break(()),
+            _ => return ControlFlow::Continue(()),
+        }
+
+        if let Some(running) = animation.take() {
+            let mut leds = running.stop();
+            if let Err(e) = leds.off() {
+                println!("Unable to turn the leds off: {}", e);
             }
-            FreeRtos::delay_ms(500);
+            m5.leds = Some(leds);
         }
-    }
+        match m5.animate(effect(index), &AnimationConfig::new()) {
+            Ok(started) => animation = Some(started),
+            Err(e) => println!("Unable to start the animation: {}", e),
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
 }
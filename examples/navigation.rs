@@ -0,0 +1,103 @@
+use std::{fmt::Debug, ops::ControlFlow};
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    event::{Event, EventLoopConfig},
+    hal::{Display, I2cBus},
+    navigation::{Encoder, InputAction, Potentiometer, ENCODER_ADDRESS},
+    screen::Screen,
+    M5Go, M5GoError,
+};
+
+const ITEMS: [&str; 4] = ["Brightness", "Volume", "Bluetooth", "About"];
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    // The menu also follows an Encoder unit on Port A and an Angle unit on Port B, when plugged
+    if let Some(bus) = m5.share_port_a() {
+        let mut encoder = bus.device();
+        if encoder.write_to(ENCODER_ADDRESS, &[], 10).is_ok() {
+            m5.navigation.add_source(Encoder::new(encoder));
+        }
+    }
+    if let Some(port_b) = m5.port_b.take() {
+        m5.navigation
+            .add_source(Potentiometer::new(port_b, ITEMS.len() as u16));
+    }
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+    let mut selected = 0;
+    draw(&mut screen, selected, None)?;
+
+    m5.run(&EventLoopConfig::new(), |m5, event| {
+        let Event::Input(action) = event else {
+            return ControlFlow::Continue(());
+        };
+        let opened = match action {
+            InputAction::Up => {
+                selected = (selected + ITEMS.len() - 1) % ITEMS.len();
+                None
+            }
+            InputAction::Down => {
+                selected = (selected + 1) % ITEMS.len();
+                None
+            }
+            InputAction::Select => Some(ITEMS[selected]),
+            InputAction::Back => None,
+        };
+        if let Err(e) = draw(&mut screen, selected, opened) {
+            println!("Unable to draw the menu: {}", e);
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
+
+fn draw<D: Display>(
+    screen: &mut Screen<D>,
+    selected: usize,
+    opened: Option<&str>,
+) -> Result<(), M5GoError>
+where
+    D::Error: Debug,
+{
+    screen.fill_background(Rgb565::BLACK)?;
+    if let Some(item) = opened {
+        screen.draw_text(
+            item,
+            Point::new(0, 15),
+            Alignment::Left,
+            Rgb565::WHITE,
+            &FONT_10X20,
+        )?;
+        return Ok(());
+    }
+    for (index, item) in ITEMS.iter().enumerate() {
+        let color = if index == selected {
+            Rgb565::YELLOW
+        } else {
+            Rgb565::WHITE
+        };
+        screen.draw_text(
+            item,
+            Point::new(0, 15 + 25 * index as i32),
+            Alignment::Left,
+            color,
+            &FONT_10X20,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use m5_go::M5Go;
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    // From now on a panic shows up on the screen, then the device reboots
+    let mut m5 = M5Go::new(peripherals)?.with_panic_hook();
+
+    if let Some(report) = m5.last_panic()? {
+        println!("Previous run {}", report);
+        m5.show_panic(&report)?;
+        FreeRtos::delay_ms(5000);
+        m5.clear_last_panic()?;
+    }
+
+    // The panic hook keeps its own handle on the screen
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+    screen.fill_background(Rgb565::BLACK)?;
+    screen.draw_text(
+        "Press A to panic",
+        Point::new(0, 15),
+        Alignment::Left,
+        Rgb565::WHITE,
+        &FONT_10X20,
+    )?;
+
+    loop {
+        if m5.button_a.is_low() {
+            let missing: Option<u8> = None;
+            missing.expect("Something went wrong");
+        }
+        FreeRtos::delay_ms(50);
+    }
+}
@@ -0,0 +1,47 @@
+/**
+ * This example drives the Grove ports through the embedded-hal and embedded-io traits, as a
+ * driver from the ecosystem would: it blinks a LED unit on Port B and echoes the lines received
+ * on Port C, reporting the I2C devices answering on Port A.
+ */
+use embedded_hal::{digital::ToggleableOutputPin, i2c::I2c};
+use embedded_io::blocking::{Read, Write};
+use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use m5_go::M5Go;
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+    let mut port_a = m5.port_a.take().expect("Port A is set up by default");
+    let mut port_b = m5.port_b.take().expect("Port B is set up by default");
+    let mut port_c = m5.port_c.take().expect("Port C is set up by default");
+
+    port_a.set_timeout(10);
+    for address in 0x08..0x78 {
+        if I2c::write(&mut port_a, address, &[]).is_ok() {
+            println!("Device at {:#04x} on Port A", address);
+        }
+    }
+
+    // Reads block until data comes in, so the echo runs in its own thread
+    std::thread::spawn(move || {
+        let mut buffer = [0; 64];
+        loop {
+            match port_c.read(&mut buffer) {
+                Ok(count) => {
+                    if let Err(e) = port_c.write_all(&buffer[..count]) {
+                        println!("Echo failed: {}", e);
+                    }
+                }
+                Err(e) => println!("Read failed: {}", e),
+            }
+        }
+    });
+
+    loop {
+        port_b.toggle()?;
+        FreeRtos::delay_ms(500);
+    }
+}
@@ -6,32 +6,35 @@ use embedded_graphics::{
 };
 use esp_idf_hal::prelude::Peripherals;
 
-fn main() {
+fn main() -> anyhow::Result<()> {
     esp_idf_sys::link_patches();
 
     let peripherals = Peripherals::take().unwrap();
 
-    let mut m5 = m5_go::M5Go::new(peripherals).unwrap();
+    let mut m5 = m5_go::M5Go::new(peripherals)?;
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
 
-    m5.screen.turn_on();
+    screen.turn_on()?;
 
-    m5.screen.fill_background(Rgb565::GREEN);
+    screen.fill_background(Rgb565::GREEN)?;
 
     let font = FONT_10X20;
 
-    let next_position = m5.screen.draw_text(
+    let next_position = screen.draw_text(
         "I am text",
         Point::new(0, 15),
         Alignment::Left,
         Rgb565::BLACK,
         &font,
-    );
+    )?;
 
-    m5.screen.draw_text(
+    screen.draw_text(
         " and i come after",
         next_position,
         Alignment::Left,
         Rgb565::BLACK,
         &font,
-    );
+    )?;
+
+    Ok(())
 }
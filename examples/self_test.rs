@@ -0,0 +1,31 @@
+/**
+ * Incoming inspection of a new unit: follow the prompts on the screen.
+ * Wire the TX and RX pins of Port C together and plug the units expected on Port A beforehand.
+ */
+use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use m5_go::{diagnostics::DiagnosticsConfig, M5Go};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    // The ENV II unit
+    let config = DiagnosticsConfig::new().expect_device(0x44);
+    let report = m5.self_test(&config)?;
+
+    if let Some(leds) = &mut m5.leds {
+        leds.fill(if report.passed() {
+            smart_leds::colors::GREEN
+        } else {
+            smart_leds::colors::RED
+        });
+        leds.display()?;
+    }
+
+    loop {
+        FreeRtos::delay_ms(1000);
+    }
+}
@@ -0,0 +1,58 @@
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use m5_go::{
+    leds::WithBrightness,
+    settings::{keys, Key},
+    M5Go,
+};
+use smart_leds::colors::WHITE;
+
+const SETTINGS_VERSION: u16 = 1;
+const BOOT_COUNT: Key<u32> = Key::new("boot_count");
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    let mut settings = m5.settings(SETTINGS_VERSION, &[])?;
+    let boot_count = settings.get_or(BOOT_COUNT, 0)? + 1;
+    settings.set(BOOT_COUNT, &boot_count)?;
+    let mut brightness = settings.get_or(keys::LED_BRIGHTNESS, 64)?;
+    drop(settings);
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+    screen.fill_background(Rgb565::BLACK)?;
+    screen.draw_text(
+        format!("Boot #{}", boot_count).as_str(),
+        Point::new(0, 15),
+        Alignment::Left,
+        Rgb565::WHITE,
+        &FONT_10X20,
+    )?;
+
+    // Button A changes the brightness, which is kept across reboots
+    loop {
+        if m5.button_a.is_low() {
+            brightness = brightness.wrapping_add(32);
+            m5.settings(SETTINGS_VERSION, &[])?
+                .set(keys::LED_BRIGHTNESS, &brightness)?;
+            FreeRtos::delay_ms(200);
+        }
+
+        if let Some(leds) = &mut m5.leds {
+            leds.fill(WHITE.with_brightness(brightness));
+            leds.display()?;
+        }
+
+        FreeRtos::delay_ms(50);
+    }
+}
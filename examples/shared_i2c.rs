@@ -0,0 +1,47 @@
+/**
+ * This example shares the internal I2C bus between the IP5306 power chip, read from the main
+ * task, and a SHT30 sensor of the Env II unit on Port A, read from another thread.
+ */
+use std::{thread, time::Duration};
+
+use embedded_hal::i2c::I2c;
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{battery::Ip5306, i2c::EspBusRecovery, M5Go};
+
+const SHT30: u8 = 0x44;
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+    let bus = m5
+        .share_port_a()
+        .expect("Port A is set up by default")
+        .with_recovery(EspBusRecovery::port_a());
+
+    // Any embedded-hal driver can take the device instead
+    let mut sensor = bus.device().timeout(10);
+    thread::spawn(move || loop {
+        let mut buffer = [0; 6];
+        match sensor.write_read(SHT30, &[0x2C, 0x06], &mut buffer) {
+            Ok(()) => {
+                let raw = u16::from_be_bytes([buffer[0], buffer[1]]) as f32;
+                println!("Temperature: {:.2}C", raw * 175. / 65535. - 45.);
+            }
+            Err(e) => println!("Sensor read failed: {}", e),
+        }
+        thread::sleep(Duration::from_secs(1));
+    });
+
+    let mut battery = Ip5306::new(bus.device());
+    loop {
+        println!(
+            "Battery: {}%, recovered the bus {} times",
+            battery.battery_level()?,
+            bus.recoveries()
+        );
+        thread::sleep(Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,57 @@
+/**
+ * This example runs on the host with
+ * `cargo run --example sim --no-default-features --features sim --target x86_64-unknown-linux-gnu`.
+ * Buttons are read from stdin, e.g. `500 A press` then `100 A release`.
+ * The screen is saved to `screen.png` when button C is pressed.
+ */
+use std::{thread, time::Duration};
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use m5_go::{hal::ToneOutput, sim::SimInput, M5Go, Note};
+use smart_leds::colors::{BLACK, WHITE};
+
+fn main() -> anyhow::Result<()> {
+    let mut m5 = M5Go::new()?;
+    let mut leds = m5.leds.take().expect("The M5Go has led bars");
+    let mut speaker = m5.speaker.take().expect("The M5Go has a speaker");
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+
+    SimInput::new(&m5.button_a, &m5.button_b, &m5.button_c).run_stdin();
+
+    screen.turn_on()?;
+    screen.fill_background(Rgb565::BLACK)?;
+    screen.draw_text(
+        format!("mac : {}", m5.mac).as_str(),
+        Point::new(0, 15),
+        Alignment::Left,
+        Rgb565::WHITE,
+        &FONT_10X20,
+    )?;
+
+    let mut lights_on = false;
+
+    loop {
+        if m5.button_a.is_low() {
+            lights_on = !lights_on;
+            leds.fill(if lights_on { WHITE } else { BLACK });
+            leds.display()?;
+            println!("leds : {:?}", leds.strip().colors());
+        }
+        if m5.button_b.is_low() {
+            speaker.play(Note::A.octave(4))?;
+            thread::sleep(Duration::from_millis(200));
+            speaker.stop()?;
+        }
+        if m5.button_c.is_low() {
+            screen.driver().save_png("screen.png")?;
+            println!("screen saved to screen.png");
+            break Ok(());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
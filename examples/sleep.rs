@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
+use m5_go::{power::Button, M5Go};
+use smart_leds::colors::BLUE;
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    let wake_cause = m5.wake_cause();
+    if let Some(screen) = &mut m5.screen {
+        screen.turn_on()?;
+        screen.fill_background(Rgb565::BLACK)?;
+        screen.draw_text(
+            format!("Woken up by {:?}", wake_cause).as_str(),
+            Point::new(0, 15),
+            Alignment::Left,
+            Rgb565::WHITE,
+            &FONT_10X20,
+        )?;
+    }
+
+    if let Some(leds) = &mut m5.leds {
+        leds.fill(BLUE);
+        leds.display()?;
+    }
+
+    FreeRtos::delay_ms(2000);
+
+    // The screen and the leds come back on after a light sleep
+    let cause = m5.light_sleep_for(Duration::from_secs(5))?;
+    println!("Light sleep ended by {:?}", cause);
+
+    FreeRtos::delay_ms(2000);
+
+    // The device reboots when button A is pressed
+    m5.deep_sleep_until_button(Button::A)
+}
@@ -7,12 +7,14 @@ use embedded_graphics::{
 use esp_idf_hal::{delay::FreeRtos, prelude::Peripherals};
 use m5_go::{speaker::Speaker, Note};
 
-fn main() {
+fn main() -> anyhow::Result<()> {
     esp_idf_sys::link_patches();
 
     let peripherals = Peripherals::take().unwrap();
 
-    let mut m5 = m5_go::M5Go::new(peripherals).unwrap();
+    let mut m5 = m5_go::M5Go::new(peripherals)?;
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    let mut buzzer = m5.speaker.take().expect("The M5Go has a speaker");
 
     let ode_to_joy = vec![
         (
@@ -86,33 +88,33 @@ fn main() {
         (vec![Note::C], 2., 4),
     ];
 
-    m5.screen.turn_on();
+    screen.turn_on()?;
 
-    m5.screen.fill_background(Rgb565::BLACK);
+    screen.fill_background(Rgb565::BLACK)?;
 
     let font = FONT_10X20;
 
-    m5.screen.draw_text(
+    screen.draw_text(
         "Here is a piece of music :",
         Point::new(0, 15),
         Alignment::Left,
         Rgb565::WHITE,
         &font,
-    );
-    m5.screen.draw_text(
+    )?;
+    screen.draw_text(
         "Ode to Joy",
         Point::new(0, 30),
         Alignment::Left,
         Rgb565::WHITE,
         &font,
-    );
-    m5.screen.draw_text(
+    )?;
+    screen.draw_text(
         "Press button A to stop it",
         Point::new(0, 45),
         Alignment::Left,
         Rgb565::WHITE,
         &font,
-    );
+    )?;
 
     let _ = 'block: {
         for (notes, speed, octave) in ode_to_joy {
@@ -121,7 +123,7 @@ fn main() {
                     break 'block;
                 }
                 let mut speaker =
-                    Speaker::speaker_from_struct(&mut m5.speaker, note.octave(octave)).unwrap();
+                    Speaker::speaker_from_struct(&mut buzzer, note.octave(octave)).unwrap();
                 speaker.set_duty(1).unwrap();
 
                 FreeRtos::delay_ms((500f32 * speed) as u32);
@@ -134,5 +136,7 @@ fn main() {
         }
     };
 
-    m5.screen.turn_off();
+    screen.turn_off()?;
+
+    Ok(())
 }
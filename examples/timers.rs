@@ -0,0 +1,68 @@
+use std::{ops::ControlFlow, time::Duration};
+
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    event::{Event, EventLoopConfig},
+    hal::{Scheduler, TimerHandle},
+    power::Button,
+    M5Go,
+};
+use smart_leds::colors::{BLACK, CYAN};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let mut m5 = M5Go::new(peripherals)?;
+
+    // The callbacks run in the timer task, they post events for the loop to handle
+    let sender = m5.events.sender();
+    let mut chaser = Some(m5.scheduler.every(Duration::from_millis(100), move || {
+        sender.send(Event::Tick).ok();
+    })?);
+
+    // Dropping the handle would cancel the callback
+    let _reminder = m5.scheduler.once(Duration::from_secs(30), || {
+        println!("Press C to leave");
+    })?;
+
+    let mut index = 0;
+
+    m5.run(&EventLoopConfig::new(), |m5, event| {
+        match event {
+            Event::Tick => {
+                if let Some(leds) = &mut m5.leds {
+                    leds.fill(BLACK);
+                    let shown = leds
+                        .set_color_at_index(index % leds.len(), CYAN)
+                        .and_then(|()| leds.display());
+                    if let Err(e) = shown {
+                        println!("Unable to display the leds: {}", e);
+                    }
+                    index += 1;
+                }
+            }
+            // Button A pauses or resumes the chaser
+            Event::ButtonPressed(Button::A) => match chaser.take() {
+                Some(timer) => {
+                    timer.cancel().ok();
+                }
+                None => {
+                    let sender = m5.events.sender();
+                    chaser = m5
+                        .scheduler
+                        .every(Duration::from_millis(100), move || {
+                            sender.send(Event::Tick).ok();
+                        })
+                        .ok();
+                }
+            },
+            Event::ButtonPressed(Button::C) => return ControlFlow::Break(()),
+            _ => (),
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
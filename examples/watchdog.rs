@@ -0,0 +1,60 @@
+use std::{ops::ControlFlow, thread, time::Duration};
+
+use embedded_graphics::{
+    mono_font::ascii::FONT_10X20,
+    pixelcolor::Rgb565,
+    prelude::{Point, RgbColor},
+    text::Alignment,
+};
+use esp_idf_hal::prelude::Peripherals;
+use m5_go::{
+    event::{Event, EventLoopConfig},
+    power::Button,
+    watchdog::WatchdogConfig,
+    M5Go,
+};
+
+fn main() -> anyhow::Result<()> {
+    esp_idf_sys::link_patches();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    // The panic hook shows which task missed its deadline
+    let mut m5 = M5Go::new(peripherals)?.with_panic_hook();
+
+    if let Some(report) = m5.crash_report()? {
+        println!("{}", report);
+        m5.clear_crash_report()?;
+    }
+
+    let watchdog = m5.start_watchdog(WatchdogConfig::new())?;
+
+    // A background task feeding its own handle
+    let worker = watchdog.register("worker", Duration::from_secs(1));
+    thread::spawn(move || loop {
+        worker.feed();
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    let mut screen = m5.screen.take().expect("The M5Go has a screen");
+    screen.turn_on()?;
+    screen.fill_background(Rgb565::BLACK)?;
+    screen.draw_text(
+        "Press A to hang",
+        Point::new(0, 15),
+        Alignment::Left,
+        Rgb565::WHITE,
+        &FONT_10X20,
+    )?;
+
+    let config = EventLoopConfig::new().watchdog_timeout(Duration::from_secs(2));
+    m5.run(&config, |_, event| {
+        if event == Event::ButtonPressed(Button::A) {
+            // Longer than the loop deadline, the watchdog panics naming the event loop
+            thread::sleep(Duration::from_secs(5));
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,903 @@
+//! Led animations drawn from a timer, so the application task never waits on them.
+//!
+//! An [`Effect`] draws a frame for a given time since it started, and reports when it is over.
+//! Effects combine with [`EffectExt`], then an [`Animation`] runs one on a strip:
+//!
+//! ```ignore
+//! let effect = Comet::new()
+//!     .palette(Palette::new(&[RED]))
+//!     .for_duration(Duration::from_secs(2))
+//!     .then(Breathing::new().period(Duration::from_secs(1)));
+//! let animation = m5.animate(effect, &AnimationConfig::new().name("boot"))?;
+//! // Event::AnimationFinished("boot") is posted once the effect is over
+//! m5.leds = Some(animation.stop());
+//! ```
+
+use std::{
+    f32::consts::PI,
+    ops::Range,
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::Duration,
+};
+
+use log::warn;
+use smart_leds::RGB8;
+
+use crate::{
+    event::Event,
+    hal::{LedStrip, Platform, Scheduler, TimerHandle},
+    leds::{Leds, WithBrightness},
+    M5Go, M5GoError,
+};
+
+/// Colors an effect picks from, blended between two neighbours
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<RGB8>,
+}
+
+impl Palette {
+    pub fn new(colors: &[RGB8]) -> Self {
+        Self {
+            colors: colors.to_vec(),
+        }
+    }
+
+    /// Red, yellow, green, cyan, blue and magenta, looping back to red
+    pub fn rainbow() -> Self {
+        Self::new(&[
+            RGB8::new(255, 0, 0),
+            RGB8::new(255, 255, 0),
+            RGB8::new(0, 255, 0),
+            RGB8::new(0, 255, 255),
+            RGB8::new(0, 0, 255),
+            RGB8::new(255, 0, 255),
+        ])
+    }
+
+    /// Black, red, orange, yellow then white, from cold to hot
+    pub fn heat() -> Self {
+        Self::new(&[
+            RGB8::new(0, 0, 0),
+            RGB8::new(160, 0, 0),
+            RGB8::new(255, 80, 0),
+            RGB8::new(255, 200, 0),
+            RGB8::new(255, 255, 160),
+        ])
+    }
+
+    pub fn colors(&self) -> &[RGB8] {
+        &self.colors
+    }
+
+    /// The color at `index`, looping over the palette
+    pub fn get(&self, index: usize) -> RGB8 {
+        if self.colors.is_empty() {
+            return RGB8::default();
+        }
+        self.colors[index % self.colors.len()]
+    }
+
+    /// The color at `position` along the palette, 1 being back to the first color
+    pub fn wrapping(&self, position: f32) -> RGB8 {
+        let scaled = position.rem_euclid(1.0) * self.colors.len() as f32;
+        let index = scaled as usize;
+        blend(self.get(index), self.get(index + 1), scaled.fract())
+    }
+
+    /// The color at `position` along the palette, from the first color at 0 to the last at 1
+    pub fn clamped(&self, position: f32) -> RGB8 {
+        if self.colors.len() < 2 {
+            return self.get(0);
+        }
+        let scaled = position.clamp(0.0, 1.0) * (self.colors.len() - 1) as f32;
+        let index = (scaled as usize).min(self.colors.len() - 2);
+        blend(self.get(index), self.get(index + 1), scaled - index as f32)
+    }
+}
+
+fn blend(from: RGB8, to: RGB8, amount: f32) -> RGB8 {
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount) as u8;
+    RGB8::new(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+    )
+}
+
+fn scale(color: RGB8, brightness: f32) -> RGB8 {
+    color.with_brightness((brightness.clamp(0.0, 1.0) * 255.0) as u8)
+}
+
+/// Cycles of `period` elapsed, with their fraction
+fn phase(elapsed: Duration, period: Duration) -> f32 {
+    elapsed.as_secs_f32() / period.as_secs_f32().max(f32::EPSILON)
+}
+
+/// A small xorshift generator, the effects only need their flicker to look random
+#[derive(Clone, Copy, Debug)]
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    /// In `0..bound`, 0 when the bound is 0
+    fn below(&mut self, bound: u32) -> u32 {
+        self.next().checked_rem(bound).unwrap_or(0)
+    }
+}
+
+/// Counts the fixed steps of a stateful effect, catching up after a late frame
+#[derive(Clone, Copy, Debug, Default)]
+struct Steps {
+    done: u32,
+}
+
+impl Steps {
+    /// At most this many steps are run for a single frame
+    const MAX_CATCH_UP: u32 = 8;
+
+    fn due(&mut self, elapsed: Duration, period: Duration) -> u32 {
+        let total = phase(elapsed, period) as u32;
+        let due = total.saturating_sub(self.done);
+        self.done = total;
+        due.min(Self::MAX_CATCH_UP)
+    }
+}
+
+/// Something drawn on a strip over time
+pub trait Effect: Send {
+    /// Draw the frame `elapsed` after the effect started, returns `false` once the effect is
+    /// over, the pixels being left untouched
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool;
+}
+
+impl<E: Effect + ?Sized> Effect for Box<E> {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        (**self).render(elapsed, pixels)
+    }
+}
+
+/// Combinators, for every effect
+pub trait EffectExt: Effect + Sized + 'static {
+    /// Stop the effect after `duration`
+    fn for_duration(self, duration: Duration) -> Timed<Self> {
+        Timed {
+            effect: self,
+            duration,
+        }
+    }
+
+    /// Run `next` once this effect is over
+    fn then<E: Effect + 'static>(self, next: E) -> Sequence {
+        Sequence::new().then(self).then(next)
+    }
+}
+
+impl<E: Effect + Sized + 'static> EffectExt for E {}
+
+/// An effect cut after some time
+pub struct Timed<E: Effect> {
+    effect: E,
+    duration: Duration,
+}
+
+impl<E: Effect> Effect for Timed<E> {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        elapsed < self.duration && self.effect.render(elapsed, pixels)
+    }
+}
+
+/// Effects run one after the other, each from its own start
+#[derive(Default)]
+pub struct Sequence {
+    effects: Vec<Box<dyn Effect>>,
+    current: usize,
+    /// When the current effect started
+    offset: Duration,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then<E: Effect + 'static>(mut self, effect: E) -> Self {
+        self.effects.push(Box::new(effect));
+        self
+    }
+}
+
+impl Effect for Sequence {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        while let Some(effect) = self.effects.get_mut(self.current) {
+            if effect.render(elapsed.saturating_sub(self.offset), pixels) {
+                return true;
+            }
+            self.current += 1;
+            self.offset = elapsed;
+        }
+        false
+    }
+}
+
+/// Effects on separate parts of the strip, like one per side bar. It is over once every part
+/// is, a part over keeping its last frame.
+#[derive(Default)]
+pub struct Segments {
+    parts: Vec<(Range<usize>, Box<dyn Effect>, bool)>,
+}
+
+impl Segments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `effect` on the leds in `range`, the first one being the closest to the data pin
+    pub fn part<E: Effect + 'static>(mut self, range: Range<usize>, effect: E) -> Self {
+        self.parts.push((range, Box::new(effect), true));
+        self
+    }
+}
+
+impl Effect for Segments {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        for (range, effect, running) in &mut self.parts {
+            let end = range.end.min(pixels.len());
+            let start = range.start.min(end);
+            if *running {
+                *running = effect.render(elapsed, &mut pixels[start..end]);
+            }
+        }
+        self.parts.iter().any(|(_, _, running)| *running)
+    }
+}
+
+/// Defines the builder methods common to the built-in effects
+macro_rules! effect_builder {
+    ($($effect:ident),*) => {
+        $(
+            impl $effect {
+                pub fn new() -> Self {
+                    Self::default()
+                }
+
+                /// One cycle of the effect, the shorter the faster
+                pub fn period(mut self, period: Duration) -> Self {
+                    self.period = period;
+                    self
+                }
+
+                pub fn palette(mut self, palette: Palette) -> Self {
+                    self.palette = palette;
+                    self
+                }
+            }
+        )*
+    };
+}
+
+effect_builder!(Rainbow, Breathing, Comet, Fire, Sparkle, TheaterChase);
+
+/// The palette scrolling along the strip, one full palette per period
+pub struct Rainbow {
+    period: Duration,
+    palette: Palette,
+}
+
+impl Default for Rainbow {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(2),
+            palette: Palette::rainbow(),
+        }
+    }
+}
+
+impl Effect for Rainbow {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        let phase = phase(elapsed, self.period);
+        let count = pixels.len().max(1) as f32;
+        for (index, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = self.palette.wrapping(phase + index as f32 / count);
+        }
+        true
+    }
+}
+
+/// The whole strip fading in and out, one breath per period and a new color of the palette for
+/// each
+pub struct Breathing {
+    period: Duration,
+    palette: Palette,
+}
+
+impl Default for Breathing {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(3),
+            palette: Palette::new(&[RGB8::new(0, 80, 255)]),
+        }
+    }
+}
+
+impl Effect for Breathing {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        let phase = phase(elapsed, self.period);
+        let brightness = (1.0 - (2.0 * PI * phase).cos()) / 2.0;
+        pixels.fill(scale(self.palette.get(phase as usize), brightness));
+        true
+    }
+}
+
+/// A dot with a fading tail running along the strip, one pass per period and a new color of
+/// the palette for each. With a tail of one led, it is a plain chase.
+pub struct Comet {
+    period: Duration,
+    palette: Palette,
+    tail: usize,
+}
+
+impl Comet {
+    /// Leds lit behind the head, the head included
+    pub fn tail(mut self, tail: usize) -> Self {
+        self.tail = tail.max(1);
+        self
+    }
+}
+
+impl Default for Comet {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(1),
+            palette: Palette::new(&[RGB8::new(255, 255, 255)]),
+            tail: 4,
+        }
+    }
+}
+
+impl Effect for Comet {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        let phase = phase(elapsed, self.period);
+        let color = self.palette.get(phase as usize);
+        // The tail leaves the strip before the next pass starts
+        let head = phase.fract() * (pixels.len() + self.tail) as f32;
+        for (index, pixel) in pixels.iter_mut().enumerate() {
+            let behind = head - index as f32;
+            *pixel = if (0.0..self.tail as f32).contains(&behind) {
+                let brightness = 1.0 - behind.floor() / self.tail as f32;
+                scale(color, brightness * brightness)
+            } else {
+                RGB8::default()
+            };
+        }
+        true
+    }
+}
+
+/// Flames rising from the first led, one step per period, colored through the palette from
+/// cold to hot
+pub struct Fire {
+    period: Duration,
+    palette: Palette,
+    /// How much the flames cool down at each step, out of 255
+    cooling: u8,
+    /// Chance of a new spark at each step, out of 255
+    sparking: u8,
+    heat: Vec<u8>,
+    random: Random,
+    steps: Steps,
+}
+
+impl Fire {
+    pub fn cooling(mut self, cooling: u8) -> Self {
+        self.cooling = cooling;
+        self
+    }
+
+    pub fn sparking(mut self, sparking: u8) -> Self {
+        self.sparking = sparking;
+        self
+    }
+
+    /// Flames with another seed flicker differently, for several fires side by side
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.random = Random(seed.max(1));
+        self
+    }
+
+    fn step(&mut self) {
+        let count = self.heat.len() as u32;
+        let cooling = self.cooling as u32 * 10 / count.max(1) + 2;
+        for heat in &mut self.heat {
+            *heat = heat.saturating_sub(self.random.below(cooling) as u8);
+        }
+
+        // Heat drifts away from the base
+        for index in (2..self.heat.len()).rev() {
+            let below = self.heat[index - 1] as u16 + 2 * self.heat[index - 2] as u16;
+            self.heat[index] = (below / 3) as u8;
+        }
+
+        if self.random.below(255) < self.sparking as u32 && !self.heat.is_empty() {
+            let index = self.random.below(count.min(3)) as usize;
+            let spark = 160 + self.random.below(96) as u8;
+            self.heat[index] = self.heat[index].saturating_add(spark);
+        }
+    }
+}
+
+impl Default for Fire {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_millis(30),
+            palette: Palette::heat(),
+            cooling: 55,
+            sparking: 120,
+            heat: Vec::new(),
+            random: Random(0x2545_f491),
+            steps: Steps::default(),
+        }
+    }
+}
+
+impl Effect for Fire {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        self.heat.resize(pixels.len(), 0);
+        for _ in 0..self.steps.due(elapsed, self.period) {
+            self.step();
+        }
+        for (pixel, heat) in pixels.iter_mut().zip(&self.heat) {
+            *pixel = self.palette.clamped(*heat as f32 / 255.0);
+        }
+        true
+    }
+}
+
+/// Random leds flashing with a color of the palette then fading, one step per period
+pub struct Sparkle {
+    period: Duration,
+    palette: Palette,
+    /// Chance of a new sparkle at each step, out of 255
+    density: u8,
+    /// Brightness kept from one step to the next, out of 255
+    fade: u8,
+    lights: Vec<RGB8>,
+    random: Random,
+    steps: Steps,
+}
+
+impl Sparkle {
+    pub fn density(mut self, density: u8) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn fade(mut self, fade: u8) -> Self {
+        self.fade = fade;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.random = Random(seed.max(1));
+        self
+    }
+
+    fn step(&mut self) {
+        for light in &mut self.lights {
+            *light = light.with_brightness(self.fade);
+        }
+        if self.random.below(255) < self.density as u32 && !self.lights.is_empty() {
+            let index = self.random.below(self.lights.len() as u32) as usize;
+            let color = self.random.below(self.palette.colors().len() as u32) as usize;
+            self.lights[index] = self.palette.get(color);
+        }
+    }
+}
+
+impl Default for Sparkle {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_millis(50),
+            palette: Palette::new(&[RGB8::new(255, 255, 255)]),
+            density: 80,
+            fade: 200,
+            lights: Vec::new(),
+            random: Random(0x9e37_79b9),
+            steps: Steps::default(),
+        }
+    }
+}
+
+impl Effect for Sparkle {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        self.lights.resize(pixels.len(), RGB8::default());
+        for _ in 0..self.steps.due(elapsed, self.period) {
+            self.step();
+        }
+        pixels.copy_from_slice(&self.lights);
+        true
+    }
+}
+
+/// Every few leds lit, the pattern crawling by one led at a time like a marquee. The lit leds
+/// go around once per period, with a new color of the palette each time.
+pub struct TheaterChase {
+    period: Duration,
+    palette: Palette,
+    spacing: usize,
+}
+
+impl TheaterChase {
+    /// One led lit out of `spacing`
+    pub fn spacing(mut self, spacing: usize) -> Self {
+        self.spacing = spacing.max(1);
+        self
+    }
+}
+
+impl Default for TheaterChase {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_millis(600),
+            palette: Palette::new(&[RGB8::new(255, 160, 0)]),
+            spacing: 3,
+        }
+    }
+}
+
+impl Effect for TheaterChase {
+    fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+        let phase = phase(elapsed, self.period);
+        let color = self.palette.get(phase as usize);
+        let offset = (phase.fract() * self.spacing as f32) as usize;
+        for (index, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = if index % self.spacing == offset {
+                color
+            } else {
+                RGB8::default()
+            };
+        }
+        true
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationConfig {
+    /// Frames drawn per second
+    pub frame_rate: u32,
+    /// Reported by [`Event::AnimationFinished`]
+    pub name: &'static str,
+    /// Where [`Event::AnimationFinished`] is posted, [`M5Go::animate`] uses the queue of the
+    /// device
+    pub events: Option<Sender<Event>>,
+}
+
+impl AnimationConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = frame_rate.max(1);
+        self
+    }
+
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn events(mut self, sender: Sender<Event>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            frame_rate: 50,
+            name: "animation",
+            events: None,
+        }
+    }
+}
+
+struct State<S: LedStrip> {
+    /// Taken back by [`Animation::stop`]
+    leds: Option<Leds<S>>,
+    effect: Box<dyn Effect>,
+    finished: bool,
+    events: Option<Sender<Event>>,
+}
+
+/// An effect running on a strip, dropping it cancels the effect along with the strip
+pub struct Animation<S: LedStrip, T: TimerHandle> {
+    state: Arc<Mutex<State<S>>>,
+    timer: T,
+    name: &'static str,
+}
+
+impl<S, T> Animation<S, T>
+where
+    S: LedStrip + Send + 'static,
+    T: TimerHandle,
+{
+    /// Draw `effect` on `leds` from a timer of `scheduler`, each frame for the time read on its
+    /// clock, so that a late timer does not slow the effect down
+    pub fn start<C, E>(
+        scheduler: &C,
+        leds: Leds<S>,
+        effect: E,
+        config: &AnimationConfig,
+    ) -> Result<Self, M5GoError>
+    where
+        C: Scheduler<Timer = T> + Clone + Send + 'static,
+        E: Effect + 'static,
+    {
+        Self::try_start(scheduler, leds, effect, config).map_err(|(e, _)| e)
+    }
+
+    /// The strip is given back with the error when the timer cannot be created
+    fn try_start<C, E>(
+        scheduler: &C,
+        leds: Leds<S>,
+        effect: E,
+        config: &AnimationConfig,
+    ) -> Result<Self, (M5GoError, Leds<S>)>
+    where
+        C: Scheduler<Timer = T> + Clone + Send + 'static,
+        E: Effect + 'static,
+    {
+        let state = Arc::new(Mutex::new(State {
+            leds: Some(leds),
+            effect: Box::new(effect),
+            finished: false,
+            events: config.events.clone(),
+        }));
+
+        let interval = Duration::from_secs(1) / config.frame_rate.max(1);
+        let name = config.name;
+        let start = scheduler.now();
+        // The first frame is drawn right away
+        draw(&state, Duration::ZERO, name);
+        let timer = {
+            let state = Arc::clone(&state);
+            let clock = scheduler.clone();
+            scheduler.every(interval, move || {
+                draw(&state, clock.now().saturating_sub(start), name)
+            })
+        };
+
+        match timer {
+            Ok(timer) => Ok(Self { state, timer, name }),
+            Err(e) => {
+                let leds = state
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .leds
+                    .take()
+                    .expect("The strip is only taken back once");
+                Err((e, leds))
+            }
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.lock().finished
+    }
+
+    /// Cancel the effect and give the strip back, as the effect left it
+    pub fn stop(self) -> Leds<S> {
+        if let Err(e) = self.timer.cancel() {
+            warn!("Unable to cancel the {} animation: {}", self.name, e);
+        }
+        self.lock()
+            .leds
+            .take()
+            .expect("The strip is only taken back once")
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<S>> {
+        // A frame is drawn whole or not at all
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Draw the frame `elapsed` after the start, in the timer task
+fn draw<S: LedStrip>(state: &Mutex<State<S>>, elapsed: Duration, name: &'static str) {
+    let mut state = state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let state = &mut *state;
+    let Some(leds) = &mut state.leds else {
+        return;
+    };
+    if state.finished {
+        return;
+    }
+
+    if !state.effect.render(elapsed, leds.pixels_mut()) {
+        state.finished = true;
+        if let Some(events) = &state.events {
+            events.send(Event::AnimationFinished(name)).ok();
+        }
+        return;
+    }
+    if let Err(e) = leds.display() {
+        warn!("Unable to draw the {} animation: {}", name, e);
+    }
+}
+
+impl<P: Platform> M5Go<P>
+where
+    P::LedStrip: Send + 'static,
+    P::Scheduler: Clone + Send + 'static,
+{
+    /// Run `effect` on the led bars, [`Animation::stop`] gives them back. The end of the effect
+    /// is posted to [`M5Go::events`] unless `config` has a queue of its own.
+    pub fn animate<E: Effect + 'static>(
+        &mut self,
+        effect: E,
+        config: &AnimationConfig,
+    ) -> Result<Animation<P::LedStrip, <P::Scheduler as Scheduler>::Timer>, M5GoError> {
+        let leds = self
+            .leds
+            .take()
+            .ok_or_else(|| M5GoError::leds("Unable to animate", "the board has no led bars"))?;
+
+        let mut config = config.clone();
+        if config.events.is_none() {
+            config.events = Some(self.events.sender());
+        }
+        Animation::try_start(&self.scheduler, leds, effect, &config).map_err(|(e, leds)| {
+            self.leds = Some(leds);
+            e
+        })
+    }
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use smart_leds::colors::{BLACK, GREEN, RED};
+
+    use super::*;
+    use crate::sim::{SimLeds, SimScheduler};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Fills the strip with its color and never ends, recording the time of every frame
+    struct Probe {
+        color: RGB8,
+        frames: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl Probe {
+        fn new(color: RGB8) -> (Self, Arc<Mutex<Vec<Duration>>>) {
+            let frames = Arc::new(Mutex::new(Vec::new()));
+            let probe = Self {
+                color,
+                frames: Arc::clone(&frames),
+            };
+            (probe, frames)
+        }
+    }
+
+    impl Effect for Probe {
+        fn render(&mut self, elapsed: Duration, pixels: &mut [RGB8]) -> bool {
+            self.frames.lock().unwrap().push(elapsed);
+            pixels.fill(self.color);
+            true
+        }
+    }
+
+    #[test]
+    fn timed_ends_after_its_duration() {
+        let (probe, frames) = Probe::new(RED);
+        let mut effect = probe.for_duration(ms(100));
+        let mut pixels = [BLACK; 2];
+
+        assert!(effect.render(ms(99), &mut pixels));
+        assert_eq!(pixels, [RED; 2]);
+
+        pixels.fill(GREEN);
+        assert!(!effect.render(ms(100), &mut pixels));
+        assert_eq!(pixels, [GREEN; 2]);
+        assert_eq!(*frames.lock().unwrap(), [ms(99)]);
+    }
+
+    #[test]
+    fn sequence_runs_each_effect_from_its_own_start() {
+        let (first, first_frames) = Probe::new(RED);
+        let (second, second_frames) = Probe::new(GREEN);
+        let mut effect = first
+            .for_duration(ms(100))
+            .then(second.for_duration(ms(50)));
+        let mut pixels = [BLACK; 2];
+
+        assert!(effect.render(ms(0), &mut pixels));
+        assert_eq!(pixels, [RED; 2]);
+        assert!(effect.render(ms(120), &mut pixels));
+        assert_eq!(pixels, [GREEN; 2]);
+        assert!(effect.render(ms(160), &mut pixels));
+        assert!(!effect.render(ms(170), &mut pixels));
+        assert!(!effect.render(ms(200), &mut pixels));
+
+        assert_eq!(*first_frames.lock().unwrap(), [ms(0)]);
+        assert_eq!(*second_frames.lock().unwrap(), [ms(0), ms(40)]);
+    }
+
+    #[test]
+    fn animation_follows_the_scheduler_clock() {
+        let scheduler = SimScheduler::default();
+        scheduler.advance(ms(1000));
+        let (probe, frames) = Probe::new(RED);
+        let config = AnimationConfig::new().frame_rate(50);
+        let animation = Animation::start(
+            &scheduler,
+            Leds::from_strip(SimLeds::new(), 3),
+            probe,
+            &config,
+        )
+        .unwrap();
+
+        scheduler.advance(ms(40));
+        assert_eq!(*frames.lock().unwrap(), [ms(0), ms(20), ms(40)]);
+
+        let leds = animation.stop();
+        assert_eq!(leds.strip().colors(), [RED; 3]);
+        scheduler.advance(ms(100));
+        assert_eq!(frames.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn animation_reports_finished() {
+        let scheduler = SimScheduler::default();
+        let (sender, receiver) = channel();
+        let (probe, _) = Probe::new(RED);
+        let config = AnimationConfig::new()
+            .frame_rate(50)
+            .name("probe")
+            .events(sender);
+        let animation = Animation::start(
+            &scheduler,
+            Leds::from_strip(SimLeds::new(), 3),
+            probe.for_duration(ms(100)),
+            &config,
+        )
+        .unwrap();
+
+        scheduler.advance(ms(80));
+        assert!(!animation.is_finished());
+        assert!(receiver.try_recv().is_err());
+
+        scheduler.advance(ms(20));
+        assert!(animation.is_finished());
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(Event::AnimationFinished("probe"))
+        ));
+
+        // Reported once, the strip keeping the last frame, which was only sent once
+        scheduler.advance(ms(100));
+        assert!(receiver.try_recv().is_err());
+        let leds = animation.stop();
+        assert_eq!(leds.strip().frames(), [vec![RED; 3]]);
+    }
+}
@@ -0,0 +1,111 @@
+//! Driver for the IP5306 power management chip, on the internal I2C bus.
+
+use crate::{
+    hal::{I2cBus, Platform},
+    ports::PortA,
+    M5Go, M5GoError,
+};
+
+const ADDRESS: u8 = 0x75;
+const TIMEOUT: u32 = 100;
+
+const REG_SYS_CTL0: u8 = 0x00;
+const REG_SYS_CTL2: u8 = 0x02;
+const REG_READ0: u8 = 0x70;
+const REG_READ1: u8 = 0x71;
+const REG_READ3: u8 = 0x78;
+
+// SYS_CTL0
+const BOOST_OUT_BIT: u8 = 0x02;
+// SYS_CTL2
+const SHUTDOWN_TIME_MASK: u8 = 0x0C;
+// READ0
+const CHARGE_ENABLE_BIT: u8 = 0x08;
+// READ1
+const CHARGE_FULL_BIT: u8 = 0x08;
+
+/// How long the chip waits under light load before cutting the power
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownTime {
+    Seconds8 = 0x00,
+    Seconds16 = 0x08,
+    Seconds32 = 0x04,
+    Seconds64 = 0x0C,
+}
+
+pub struct Ip5306<I: I2cBus> {
+    bus: I,
+}
+
+impl<I: I2cBus> Ip5306<I> {
+    /// Use `&mut port_a` as the bus to keep using Port A afterwards
+    pub fn new(bus: I) -> Self {
+        Self { bus }
+    }
+
+    pub fn release(self) -> I {
+        self.bus
+    }
+
+    /// Battery level in percent, the chip reports it in steps of 25
+    pub fn battery_level(&mut self) -> Result<u8, M5GoError> {
+        let level = match self.read(REG_READ3)? & 0xF0 {
+            0x00 => 100,
+            0x80 => 75,
+            0xC0 => 50,
+            0xE0 => 25,
+            _ => 0,
+        };
+        Ok(level)
+    }
+
+    /// Whether the device is powered through USB
+    pub fn is_usb_powered(&mut self) -> Result<bool, M5GoError> {
+        Ok(self.read(REG_READ0)? & CHARGE_ENABLE_BIT != 0)
+    }
+
+    pub fn is_charging(&mut self) -> Result<bool, M5GoError> {
+        Ok(self.is_usb_powered()? && !self.is_full()?)
+    }
+
+    pub fn is_full(&mut self) -> Result<bool, M5GoError> {
+        Ok(self.read(REG_READ1)? & CHARGE_FULL_BIT != 0)
+    }
+
+    /// Keep the power on under light load, otherwise the chip cuts it after the shutdown time
+    pub fn set_boost_keep_on(&mut self, keep_on: bool) -> Result<(), M5GoError> {
+        self.update(
+            REG_SYS_CTL0,
+            BOOST_OUT_BIT,
+            if keep_on { BOOST_OUT_BIT } else { 0 },
+        )
+    }
+
+    pub fn set_auto_power_off(&mut self, time: ShutdownTime) -> Result<(), M5GoError> {
+        self.update(REG_SYS_CTL2, SHUTDOWN_TIME_MASK, time as u8)
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, M5GoError> {
+        let mut buffer = [0];
+        self.bus
+            .write_read(ADDRESS, &[register], &mut buffer, TIMEOUT)?;
+        Ok(buffer[0])
+    }
+
+    fn update(&mut self, register: u8, mask: u8, value: u8) -> Result<(), M5GoError> {
+        let current = self.read(register)?;
+        self.bus.write_to(
+            ADDRESS,
+            &[register, (current & !mask) | (value & mask)],
+            TIMEOUT,
+        )
+    }
+}
+
+impl<P: Platform> M5Go<P> {
+    /// The power chip, `None` when Port A, which shares its bus, was left unclaimed or moved to a
+    /// [`SharedI2c`](crate::i2c::SharedI2c), give it a device of the shared bus then
+    pub fn battery(&mut self) -> Option<Ip5306<&mut PortA<P::PortA>>> {
+        self.port_a.as_mut().map(Ip5306::new)
+    }
+}
@@ -1,6 +1,10 @@
 use std::{
     cell::RefCell,
-    sync::{mpsc::sync_channel, Arc, Mutex, MutexGuard, TryLockError},
+    sync::{
+        mpsc::{sync_channel, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 use esp_idf_ble::{
@@ -8,11 +12,20 @@ use esp_idf_ble::{
     GattDescriptor, GattService, GattServiceEvent, ServiceUuid,
 };
 use esp_idf_hal::delay::FreeRtos;
-use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs::EspDefaultNvsPartition};
+use esp_idf_svc::{eventloop::EspSystemEventLoop, log::EspLogger, nvs::EspDefaultNvsPartition};
 
 use esp_idf_sys::*;
 
-use log::{info, warn};
+use log::{info, warn, LevelFilter};
+
+use crate::{crash::CrashReport, event::Event, watchdog::Watchdog, M5GoError};
+
+/// Read-only characteristic serving the crash report
+const CRASH_REPORT_UUID: u16 = 0xff02;
+/// Bytes of a full read response with the default ATT MTU of 23 bytes, the smallest there is
+const MIN_READ_LEN: usize = 22;
+/// Longest the read and write handlers may take once watched
+const HANDLER_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub struct Ble {
     ble: EspBle,
@@ -23,6 +36,10 @@ pub struct Ble {
 pub struct BleConfig {
     pub on_receive: Option<Box<dyn Fn(&[u8]) -> Option<String> + Send + Sync>>,
     commands: Vec<String>,
+    events: Option<Sender<Event>>,
+    pub(crate) name: Option<String>,
+    crash_report: Option<String>,
+    watchdog: Option<Watchdog>,
 }
 
 impl BleConfig {
@@ -38,6 +55,31 @@ impl BleConfig {
         self
     }
 
+    /// Name advertised to the clients
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Serve the report on the `0xFF02` characteristic, long reads are supported. Once a client
+    /// read it whole, an [`Event::CrashReportRead`] is posted.
+    pub fn crash_report(mut self, report: &CrashReport) -> Self {
+        self.crash_report = Some(report.to_string());
+        self
+    }
+
+    /// Watch the read and write handlers, `setup_ble` does it once the watchdog is started
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Post the writes, connections and disconnections to an event queue
+    pub fn events(mut self, sender: Sender<Event>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
     pub fn send(&mut self, command: String) {
         self.commands.push(command);
     }
@@ -45,51 +87,92 @@ impl BleConfig {
     fn next_command(&mut self) -> Option<String> {
         self.commands.pop()
     }
+
+    fn notify(&self, event: Event) {
+        if let Some(events) = &self.events {
+            // Nobody is listening once the receiver is dropped
+            events.send(event).ok();
+        }
+    }
 }
 
 impl Ble {
-    pub fn new(config: BleConfig) -> Self {
-        esp_idf_svc::log::EspLogger::initialize_default();
+    /// `nvs` is shared with the settings, see [`EspStorage::partition`](crate::settings::EspStorage::partition)
+    pub fn new(mut config: BleConfig, nvs: EspDefaultNvsPartition) -> Result<Self, M5GoError> {
+        // Keep the logger already installed, such as the `LogConsole`
+        static LOGGER: EspLogger = EspLogger;
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(LevelFilter::Info);
+        }
 
         #[allow(unused)]
-        let sys_loop_stack = Arc::new(EspSystemEventLoop::take().expect("Unable to init sys_loop"));
+        let sys_loop_stack = Arc::new(
+            EspSystemEventLoop::take().map_err(|e| M5GoError::ble("Unable to init sys_loop", e))?,
+        );
 
-        #[allow(unused)]
-        let default_nvs = Arc::new(EspDefaultNvsPartition::take().unwrap());
+        let default_nvs = Arc::new(nvs);
 
         FreeRtos::delay_us(100_u32);
 
-        let mut ble = EspBle::new("ESP32".into(), default_nvs).unwrap();
-
+        let name = config.name.clone().unwrap_or_else(|| "ESP32".to_string());
+        let mut ble =
+            EspBle::new(name, default_nvs).map_err(|e| M5GoError::ble("Unable to init BLE", e))?;
+
+        let crash_report = config.crash_report.take();
+        let read_watchdog = config
+            .watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.register_disarmed("BLE read handler", HANDLER_TIMEOUT));
+        let write_watchdog = config
+            .watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.register_disarmed("BLE write handler", HANDLER_TIMEOUT));
         let config = Arc::new(Mutex::new(RefCell::new(config)));
         let read_config = Arc::clone(&config);
         let write_config = Arc::clone(&config);
+        let connect_config = Arc::clone(&config);
 
         let (s, r) = sync_channel(1);
 
         ble.register_gatt_service_application(1, move |gatts_if, reg| {
             if let GattServiceEvent::Register(reg) = reg {
                 info!("Service registered with {:?}", reg);
-                s.send(gatts_if).expect("Unable to send result");
+                if let Err(e) = s.send(gatts_if) {
+                    warn!("Unable to send result: {:?}", e);
+                }
             } else {
                 warn!("What are you doing here??");
             }
         })
-        .expect("Unable to register service");
+        .map_err(|e| M5GoError::ble("Unable to register service", e))?;
 
         let svc_uuid = BtUuid::Uuid16(ServiceUuid::Battery as u16);
 
-        let svc = GattService::new_primary(svc_uuid, 4, 1);
+        // The service, two characteristics with their values and a descriptor
+        let svc = GattService::new_primary(svc_uuid, 6, 1);
 
         info!("GattService to be created: {:?}", svc);
 
-        let gatts_if = r.recv().expect("Unable to receive value");
+        let gatts_if = r
+            .recv()
+            .map_err(|e| M5GoError::ble("Unable to receive value", e))?;
 
         let (s, r) = sync_channel(1);
 
-        ble.register_connect_handler(gatts_if, |_gatts_if, connect| {
-            if let GattServiceEvent::Connect(connect) = connect {
-                info!("Connect event: {:?}", connect);
+        ble.register_connect_handler(gatts_if, move |_gatts_if, connect| {
+            let event = match connect {
+                GattServiceEvent::Connect(connect) => {
+                    info!("Connect event: {:?}", connect);
+                    Event::BleConnected
+                }
+                GattServiceEvent::Disconnect(disconnect) => {
+                    info!("Disconnect event: {:?}", disconnect);
+                    Event::BleDisconnected
+                }
+                _ => return,
+            };
+            if let Ok(config) = connect_config.try_lock() {
+                config.borrow().notify(event);
             }
         });
 
@@ -99,19 +182,23 @@ impl Ble {
                     "Service created with {{ \tgatts_if: {}\tstatus: {}\n\thandle: {}\n}}",
                     gatts_if, create.status, create.service_handle
                 );
-                s.send(create.service_handle).expect("Unable to send value");
+                if let Err(e) = s.send(create.service_handle) {
+                    warn!("Unable to send value: {:?}", e);
+                }
             }
         })
-        .expect("Unable to create service");
+        .map_err(|e| M5GoError::ble("Unable to create service", e))?;
 
-        let svc_handle = r.recv().expect("Unable to receive value");
+        let svc_handle = r
+            .recv()
+            .map_err(|e| M5GoError::ble("Unable to receive value", e))?;
 
         ble.start_service(svc_handle, |_, start| {
             if let GattServiceEvent::StartComplete(start) = start {
                 info!("Service started for handle: {}", start.service_handle);
             }
         })
-        .expect("Unable to start ble service");
+        .map_err(|e| M5GoError::ble("Unable to start ble service", e))?;
 
         let attr_value: AttributeValue<12> = AttributeValue::new_with_value(&[
             0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x20, 0x57, 0x6F, 0x72, 0x6C, 0x64,
@@ -129,16 +216,20 @@ impl Ble {
         ble.add_characteristic(svc_handle, charac, move |_, add_char| {
             if let GattServiceEvent::AddCharacteristicComplete(add_char) = add_char {
                 info!("Attr added with handle: {}", add_char.attr_handle);
-                s.send(add_char.attr_handle).expect("Unable to send value");
+                if let Err(e) = s.send(add_char.attr_handle) {
+                    warn!("Unable to send value: {:?}", e);
+                }
             }
         })
-        .expect("Unable to add characteristic");
+        .map_err(|e| M5GoError::ble("Unable to add characteristic", e))?;
 
-        let char_attr_handle = r.recv().expect("Unable to recv attr_handle");
+        let char_attr_handle = r
+            .recv()
+            .map_err(|e| M5GoError::ble("Unable to recv attr_handle", e))?;
 
         let data = ble
             .read_attribute_value(char_attr_handle)
-            .expect("Unable to read characteristic value");
+            .map_err(|e| M5GoError::ble("Unable to read characteristic value", e))?;
         info!("Characteristic values: {:?}", data);
 
         let cdesc = GattDescriptor::new(
@@ -150,9 +241,67 @@ impl Ble {
                 info!("Descriptor added with handle: {}", add_desc.attr_handle);
             }
         })
-        .expect("Unable to add characteristic");
+        .map_err(|e| M5GoError::ble("Unable to add characteristic", e))?;
+
+        if let Some(report) = crash_report {
+            let (s, r) = sync_channel(1);
+
+            let attr_value: AttributeValue<1> = AttributeValue::new_with_value(&[0]);
+            let charac = GattCharacteristic::new(
+                BtUuid::Uuid16(CRASH_REPORT_UUID),
+                ESP_GATT_PERM_READ as _,
+                ESP_GATT_CHAR_PROP_BIT_READ as _,
+                attr_value,
+                AutoResponse::ByApp,
+            );
+
+            ble.add_characteristic(svc_handle, charac, move |_, add_char| {
+                if let GattServiceEvent::AddCharacteristicComplete(add_char) = add_char {
+                    info!("Crash report added with handle: {}", add_char.attr_handle);
+                    if let Err(e) = s.send(add_char.attr_handle) {
+                        warn!("Unable to send value: {:?}", e);
+                    }
+                }
+            })
+            .map_err(|e| M5GoError::ble("Unable to add the crash report characteristic", e))?;
+
+            let report_handle = r
+                .recv()
+                .map_err(|e| M5GoError::ble("Unable to recv attr_handle", e))?;
+
+            let report_config = Arc::clone(&config);
+            let previous_offset = Mutex::new(None);
+            ble.register_read_handler(report_handle, move |gatts_if, read| {
+                if let GattServiceEvent::Read(read) = read {
+                    // Long reports are read in several requests, each one from an offset
+                    let offset = (read.offset as usize).min(report.len());
+                    if let Err(e) = esp_idf_ble::send(
+                        gatts_if,
+                        report_handle,
+                        read.conn_id,
+                        read.trans_id,
+                        esp_gatt_status_t_ESP_GATT_OK,
+                        &report.as_bytes()[offset..],
+                    ) {
+                        warn!("Unable to send the crash report: {:?}", e);
+                        return;
+                    }
+
+                    let mut previous_offset =
+                        previous_offset.lock().unwrap_or_else(|p| p.into_inner());
+                    let read_to_end = reads_to_end(report.len(), offset, *previous_offset);
+                    *previous_offset = Some(offset);
+                    if read_to_end {
+                        if let Ok(config) = report_config.try_lock() {
+                            config.borrow().notify(Event::CrashReportRead);
+                        }
+                    }
+                }
+            });
+        }
 
         ble.register_read_handler(char_attr_handle, move |gatts_if, read| {
+            let _watch = read_watchdog.as_ref().map(|watchdog| watchdog.watch());
             let val = read_config
                 .try_lock()
                 .ok()
@@ -160,19 +309,21 @@ impl Ble {
                 .unwrap_or("NONE".to_string());
 
             if let GattServiceEvent::Read(read) = read {
-                esp_idf_ble::send(
+                if let Err(e) = esp_idf_ble::send(
                     gatts_if,
                     char_attr_handle,
                     read.conn_id,
                     read.trans_id,
                     esp_gatt_status_t_ESP_GATT_OK,
-                    &val.as_bytes(),
-                )
-                .expect("Unable to send read response");
+                    val.as_bytes(),
+                ) {
+                    warn!("Unable to send read response: {:?}", e);
+                }
             }
         });
 
         ble.register_write_handler(char_attr_handle, move |gatts_if, write| {
+            let _watch = write_watchdog.as_ref().map(|watchdog| watchdog.watch());
             if let GattServiceEvent::Write(write) = write {
                 if write.is_prep {
                     warn!("Unsupported write");
@@ -180,11 +331,9 @@ impl Ble {
                     let value =
                         unsafe { std::slice::from_raw_parts(write.value, write.len as usize) };
                     let back = write_config.try_lock().ok().and_then(|config| {
-                        config
-                            .borrow_mut()
-                            .on_receive
-                            .as_ref()
-                            .and_then(|f| f(value))
+                        let config = config.borrow();
+                        config.notify(Event::BleWrite(value.to_vec()));
+                        config.on_receive.as_ref().and_then(|f| f(value))
                     });
                     info!(
                         "Write event received for {} with: {:?}",
@@ -196,15 +345,16 @@ impl Ble {
                         let back = back.unwrap_or(String::new());
                         info!("need rsp");
                         info!("Sending back response: {:?}", back);
-                        esp_idf_ble::send(
+                        if let Err(e) = esp_idf_ble::send(
                             gatts_if,
                             char_attr_handle,
                             write.conn_id,
                             write.trans_id,
                             esp_gatt_status_t_ESP_GATT_OK,
-                            &back.as_bytes(),
-                        )
-                        .expect("Unable to send response");
+                            back.as_bytes(),
+                        ) {
+                            warn!("Unable to send response: {:?}", e);
+                        }
                     }
                 }
             }
@@ -225,7 +375,7 @@ impl Ble {
         ble.configure_advertising_data(adv_data, |_| {
             info!("advertising configured");
         })
-        .expect("Failed to configure advertising data");
+        .map_err(|e| M5GoError::ble("Failed to configure advertising data", e))?;
 
         let scan_rsp_data = AdvertiseData {
             include_name: false,
@@ -241,24 +391,38 @@ impl Ble {
         ble.configure_advertising_data(scan_rsp_data, |_| {
             info!("Advertising configured");
         })
-        .expect("Failed to configure advertising data");
+        .map_err(|e| M5GoError::ble("Failed to configure advertising data", e))?;
 
-        Self { ble, config }
+        Ok(Self { ble, config })
     }
 
-    pub fn start(&self) -> Result<(), EspError> {
-        self.ble.start_advertise(|_| {
-            info!("advertising started");
-        })
+    pub fn start(&self) -> Result<(), M5GoError> {
+        self.ble
+            .start_advertise(|_| {
+                info!("advertising started");
+            })
+            .map_err(|e| M5GoError::ble("Unable to start advertising", e))
     }
 
-    pub fn send(
-        &self,
-        command: String,
-    ) -> Result<(), TryLockError<MutexGuard<'_, RefCell<BleConfig>>>> {
-        self.config.try_lock().and_then(|config| {
-            config.borrow_mut().send(command);
-            Ok(())
-        })
+    pub fn send(&self, command: String) -> Result<(), M5GoError> {
+        let config = self
+            .config
+            .try_lock()
+            .map_err(|e| M5GoError::ble("Unable to queue command", e))?;
+        config.borrow_mut().send(command);
+        Ok(())
     }
 }
+
+/// Whether the response to a read from `offset` holds the rest of a `len` bytes value
+///
+/// A client reads on while the responses are full, and a full response holds what separates
+/// its offset from the previous one. The first read only counts when the rest fits in the
+/// smallest response.
+fn reads_to_end(len: usize, offset: usize, previous_offset: Option<usize>) -> bool {
+    let full = match previous_offset {
+        Some(previous_offset) if offset > previous_offset => offset - previous_offset,
+        _ => MIN_READ_LEN,
+    };
+    len - offset <= full
+}
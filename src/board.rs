@@ -0,0 +1,152 @@
+//! Pin maps and feature sets of the M5Stack ESP32 cores.
+//!
+//! Every supported core shares the screen, buttons, speaker and internal I2C wiring, so the
+//! builder sets those up on their fixed pins. What differs is what is attached to them: the
+//! M5GO bottom adds the LED bars and Ports B and C, the Gray, Fire and M5Go have an IMU on the
+//! internal bus.
+
+/// The WS2812 led bars of the M5GO bottom, always wired to GPIO15
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedBars {
+    pub count: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImuModel {
+    Mpu6886,
+    Mpu9250,
+}
+
+/// An IMU on the internal I2C bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Imu {
+    pub model: ImuModel,
+    pub address: u8,
+}
+
+/// What is plugged on the core, the pins are the same on every core
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinMap {
+    /// Port B on GPIO26 and GPIO36
+    pub port_b: bool,
+    /// Port C on GPIO17 and GPIO16
+    pub port_c: bool,
+    pub leds: Option<LedBars>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Features {
+    pub imu: Option<Imu>,
+}
+
+pub trait BoardProfile {
+    fn name(&self) -> &'static str;
+
+    fn pins(&self) -> PinMap;
+
+    fn features(&self) -> Features;
+}
+
+const CORE_PINS: PinMap = PinMap {
+    port_b: false,
+    port_c: false,
+    leds: None,
+};
+
+const M5GO_BOTTOM_LEDS: LedBars = LedBars { count: 10 };
+
+/// The core alone, without IMU
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Basic;
+
+impl BoardProfile for Basic {
+    fn name(&self) -> &'static str {
+        "M5Stack Basic"
+    }
+
+    fn pins(&self) -> PinMap {
+        CORE_PINS
+    }
+
+    fn features(&self) -> Features {
+        Features { imu: None }
+    }
+}
+
+/// The Basic core with a 9-axis IMU
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Gray;
+
+impl BoardProfile for Gray {
+    fn name(&self) -> &'static str {
+        "M5Stack Gray"
+    }
+
+    fn pins(&self) -> PinMap {
+        CORE_PINS
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            imu: Some(Imu {
+                model: ImuModel::Mpu9250,
+                address: 0x68,
+            }),
+        }
+    }
+}
+
+/// The core with PSRAM, an IMU and the M5GO bottom. The PSRAM uses GPIO16 and GPIO17, so Port C
+/// is not available.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fire;
+
+impl BoardProfile for Fire {
+    fn name(&self) -> &'static str {
+        "M5Stack Fire"
+    }
+
+    fn pins(&self) -> PinMap {
+        PinMap {
+            port_b: true,
+            leds: Some(M5GO_BOTTOM_LEDS),
+            ..CORE_PINS
+        }
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            imu: Some(Imu {
+                model: ImuModel::Mpu6886,
+                address: 0x68,
+            }),
+        }
+    }
+}
+
+/// The core with an IMU and the M5GO bottom
+#[derive(Clone, Copy, Debug, Default)]
+pub struct M5GoBoard;
+
+impl BoardProfile for M5GoBoard {
+    fn name(&self) -> &'static str {
+        "M5Go"
+    }
+
+    fn pins(&self) -> PinMap {
+        PinMap {
+            port_b: true,
+            port_c: true,
+            leds: Some(M5GO_BOTTOM_LEDS),
+        }
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            imu: Some(Imu {
+                model: ImuModel::Mpu6886,
+                address: 0x68,
+            }),
+        }
+    }
+}
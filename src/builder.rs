@@ -0,0 +1,361 @@
+use esp_idf_hal::{
+    adc::{ADC1, ADC2},
+    gpio::{
+        Gpio0, Gpio12, Gpio13, Gpio14, Gpio15, Gpio16, Gpio17, Gpio18, Gpio19, Gpio2, Gpio21,
+        Gpio22, Gpio23, Gpio25, Gpio26, Gpio27, Gpio32, Gpio33, Gpio34, Gpio35, Gpio36, Gpio4,
+        Gpio5, Gpio8, Pin, PinDriver,
+    },
+    i2c::{I2cConfig, I2cDriver, I2C0, I2C1},
+    ledc::{CHANNEL0, CHANNEL1, CHANNEL2, CHANNEL3, TIMER0, TIMER1, TIMER2, TIMER3},
+    modem::Modem,
+    prelude::Peripherals,
+    spi::{SPI2, SPI3},
+    uart::{UartConfig, UartDriver, UART1, UART2},
+    units::Hertz,
+};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+
+use crate::{
+    board::{BoardProfile, Features, M5GoBoard, PinMap},
+    buttons::Buttons,
+    event::EventQueue,
+    io::IOPort,
+    leds::{LedConfig, Leds},
+    mac::MacAddress,
+    navigation::Navigation,
+    ports::{PortA, PortB, PortC},
+    power::EspPower,
+    scheduler::EspScheduler,
+    screen::{Screen, ScreenConfig, ScreenOrientation},
+    settings::EspStorage,
+    speaker::Speaker,
+    EspPlatform, M5Go, M5GoError,
+};
+
+/// Peripherals the [`M5GoBuilder`] did not use
+pub struct Unclaimed {
+    /// I2C0 with its SDA and SCL pins, when Port A was skipped
+    pub port_a: Option<(I2C0, Gpio21, Gpio22)>,
+    /// The GPIO and ADC pins with ADC1, when Port B was skipped
+    pub port_b: Option<(Gpio26, Gpio36, ADC1)>,
+    /// UART2 with its TX and RX pins, when Port C was skipped
+    pub port_c: Option<(UART2, Gpio17, Gpio16)>,
+    /// SPI2 with the CS, DC, reset, backlight, SCLK and MOSI pins, when the screen was skipped
+    pub screen: Option<(SPI2, Gpio14, Gpio27, Gpio33, Gpio32, Gpio18, Gpio23)>,
+    /// The speaker pin with LEDC channel 0 and timer 0, when the speaker was skipped
+    pub speaker: Option<(Gpio25, CHANNEL0, TIMER0)>,
+    /// The data pin of the led bars, when they were skipped or the board has none
+    pub leds: Option<Gpio15>,
+    pub i2c1: I2C1,
+    pub uart1: UART1,
+    pub spi3: SPI3,
+    pub adc2: ADC2,
+    pub modem: Modem,
+    pub ledc_channels: (CHANNEL1, CHANNEL2, CHANNEL3),
+    pub ledc_timers: (TIMER1, TIMER2, TIMER3),
+    pub gpio0: Gpio0,
+    pub gpio2: Gpio2,
+    pub gpio4: Gpio4,
+    pub gpio5: Gpio5,
+    pub gpio12: Gpio12,
+    pub gpio13: Gpio13,
+    pub gpio19: Gpio19,
+    pub gpio34: Gpio34,
+    pub gpio35: Gpio35,
+}
+
+/// Sets up an [`M5Go`] with only the peripherals and settings the application needs
+///
+/// ```ignore
+/// let (m5, unclaimed) = M5GoBuilder::new(peripherals)
+///     .port_c_baudrate(115_200)
+///     .without_port_a()
+///     .build()?;
+/// ```
+pub struct M5GoBuilder {
+    peripherals: Peripherals,
+    pins: PinMap,
+    features: Features,
+    port_a: Option<I2cConfig>,
+    port_b: bool,
+    port_c: Option<u32>,
+    screen: Option<ScreenConfig>,
+    speaker: bool,
+    leds: bool,
+    led_count: Option<usize>,
+}
+
+impl M5GoBuilder {
+    pub fn new(peripherals: Peripherals) -> Self {
+        Self {
+            peripherals,
+            pins: M5GoBoard.pins(),
+            features: M5GoBoard.features(),
+            port_a: Some(I2cConfig::new()),
+            port_b: true,
+            port_c: Some(9600),
+            screen: Some(ScreenConfig::new()),
+            speaker: true,
+            leds: true,
+            led_count: None,
+        }
+    }
+
+    /// Set up the peripherals of another core, [`M5GoBoard`] being the default
+    ///
+    /// Ports and led bars the board does not have are left unclaimed.
+    pub fn board<B: BoardProfile>(mut self, board: B) -> Self {
+        self.pins = board.pins();
+        self.features = board.features();
+        self
+    }
+
+    /// Leave I2C0, GPIO21 and GPIO22 unclaimed
+    pub fn without_port_a(mut self) -> Self {
+        self.port_a = None;
+        self
+    }
+
+    /// Leave GPIO26, GPIO36 and ADC1 unclaimed
+    pub fn without_port_b(mut self) -> Self {
+        self.port_b = false;
+        self
+    }
+
+    /// Leave UART2, GPIO16 and GPIO17 unclaimed
+    pub fn without_port_c(mut self) -> Self {
+        self.port_c = None;
+        self
+    }
+
+    /// Leave SPI2, GPIO14, GPIO18, GPIO23, GPIO27, GPIO32 and GPIO33 unclaimed
+    pub fn without_screen(mut self) -> Self {
+        self.screen = None;
+        self
+    }
+
+    /// Leave GPIO25, LEDC channel 0 and timer 0 unclaimed
+    pub fn without_speaker(mut self) -> Self {
+        self.speaker = false;
+        self
+    }
+
+    /// Leave GPIO15 unclaimed
+    pub fn without_leds(mut self) -> Self {
+        self.leds = false;
+        self
+    }
+
+    /// I2C clock of Port A in Hz, enables the port if it was skipped
+    pub fn port_a_baudrate(mut self, baudrate: u32) -> Self {
+        self.port_a = Some(I2cConfig::new().baudrate(Hertz(baudrate)));
+        self
+    }
+
+    /// Baud rate of Port C, enables the port if it was skipped
+    pub fn port_c_baudrate(mut self, baudrate: u32) -> Self {
+        self.port_c = Some(baudrate);
+        self
+    }
+
+    /// Enables the screen if it was skipped
+    pub fn screen_orientation(mut self, orientation: ScreenOrientation) -> Self {
+        self.screen
+            .get_or_insert_with(ScreenConfig::new)
+            .orientation = orientation;
+        self
+    }
+
+    /// SPI clock of the screen in Hz, enables the screen if it was skipped
+    pub fn screen_baudrate(mut self, baudrate: u32) -> Self {
+        self.screen.get_or_insert_with(ScreenConfig::new).baudrate = baudrate;
+        self
+    }
+
+    /// Number of leds on the side bars, enables them if they were skipped. Ignored on boards
+    /// without them.
+    pub fn led_count(mut self, count: usize) -> Self {
+        self.leds = true;
+        self.led_count = Some(count);
+        self
+    }
+
+    pub fn build<'a>(self) -> Result<(M5Go<EspPlatform<'a>>, Unclaimed), M5GoError> {
+        let peripherals = self.peripherals;
+
+        let mac = MacAddress::wifi_sta()?;
+        let bt_mac = MacAddress::bluetooth()?;
+
+        // Port A
+        let (port_a, unclaimed_a) = match self.port_a {
+            Some(config) => {
+                let i2c = peripherals.i2c0;
+                let sda = peripherals.pins.gpio21;
+                let scl = peripherals.pins.gpio22;
+                let port_a = I2cDriver::new(i2c, sda, scl, &config)
+                    .map_err(|e| M5GoError::i2c("Unable to set up Port A", e))?;
+                (Some(PortA::new(port_a)), None)
+            }
+            None => (
+                None,
+                Some((
+                    peripherals.i2c0,
+                    peripherals.pins.gpio21,
+                    peripherals.pins.gpio22,
+                )),
+            ),
+        };
+
+        // Port C
+        let (port_c, unclaimed_c) = match self.port_c.filter(|_| self.pins.port_c) {
+            Some(baudrate) => {
+                let port_c_config = UartConfig::new().baudrate(Hertz(baudrate));
+                let port_c = UartDriver::new(
+                    peripherals.uart2,
+                    peripherals.pins.gpio17,
+                    peripherals.pins.gpio16,
+                    None as Option<Gpio8>,
+                    None as Option<Gpio8>,
+                    &port_c_config,
+                )
+                .map_err(|e| M5GoError::uart("Unable to set up Port C", e))?;
+                (Some(PortC::new(port_c)), None)
+            }
+            None => (
+                None,
+                Some((
+                    peripherals.uart2,
+                    peripherals.pins.gpio17,
+                    peripherals.pins.gpio16,
+                )),
+            ),
+        };
+
+        // Port B
+        let io_b = peripherals.pins.gpio26;
+        let input_b = peripherals.pins.gpio36;
+        let adc1 = peripherals.adc1;
+        let (port_b, unclaimed_b) = if self.port_b && self.pins.port_b {
+            (Some(PortB::new(IOPort::new(io_b, input_b, adc1)?)), None)
+        } else {
+            (None, Some((io_b, input_b, adc1)))
+        };
+
+        // Buttons
+        let button_a = PinDriver::input(peripherals.pins.gpio39)
+            .map_err(|e| M5GoError::gpio("Unable to set up button A", e))?;
+        let button_b = PinDriver::input(peripherals.pins.gpio38)
+            .map_err(|e| M5GoError::gpio("Unable to set up button B", e))?;
+        let button_c = PinDriver::input(peripherals.pins.gpio37)
+            .map_err(|e| M5GoError::gpio("Unable to set up button C", e))?;
+
+        // Screen
+        let blk = peripherals.pins.gpio32;
+        let sclk = peripherals.pins.gpio18;
+        let sdo = peripherals.pins.gpio23;
+        let cs = peripherals.pins.gpio14;
+        let dc = peripherals.pins.gpio27;
+        let reset = peripherals.pins.gpio33;
+        let spi2 = peripherals.spi2;
+
+        let (screen, unclaimed_screen) = match &self.screen {
+            Some(config) => (
+                Some(Screen::new(cs, sdo, sclk, dc, reset, blk, spi2, config)?),
+                None,
+            ),
+            None => (None, Some((spi2, cs, dc, reset, blk, sclk, sdo))),
+        };
+
+        // Leds, the RMT driver takes the pin by its number
+        let led_pin = peripherals.pins.gpio15;
+        let (leds, unclaimed_leds) = match self.pins.leds.filter(|_| self.leds) {
+            Some(bars) => (
+                Some(Leds::new(
+                    &LedConfig::new()
+                        .pin(led_pin.pin())
+                        .count(self.led_count.unwrap_or(bars.count)),
+                )?),
+                None,
+            ),
+            None => (None, Some(led_pin)),
+        };
+
+        // Speaker
+        let speaker_pin = peripherals.pins.gpio25;
+        let channel0 = peripherals.ledc.channel0;
+        let timer0 = peripherals.ledc.timer0;
+        let (speaker, unclaimed_speaker) = if self.speaker {
+            (Some(Speaker::new(speaker_pin, channel0, timer0)), None)
+        } else {
+            (None, Some((speaker_pin, channel0, timer0)))
+        };
+
+        // Power management
+        let power = EspPower::new([button_a.pin(), button_b.pin(), button_c.pin()]);
+
+        let scheduler = EspScheduler::new()?;
+
+        // Settings, the partition is shared with BLE
+        let nvs = EspDefaultNvsPartition::take()
+            .map_err(|e| M5GoError::storage("Unable to take the NVS partition", e))?;
+        let storage = EspStorage::new(nvs)?;
+
+        let m5 = M5Go {
+            button_a,
+            button_b,
+            button_c,
+            buttons: Buttons::default(),
+            navigation: Navigation::default(),
+            leds,
+            screen,
+            port_a,
+            port_b,
+            port_c,
+            speaker,
+            power,
+            scheduler,
+            storage,
+            ble: None,
+            mac,
+            bt_mac,
+            imu: self.features.imu,
+            events: EventQueue::new(),
+            watchdog: None,
+        };
+
+        let unclaimed = Unclaimed {
+            port_a: unclaimed_a,
+            port_b: unclaimed_b,
+            port_c: unclaimed_c,
+            screen: unclaimed_screen,
+            speaker: unclaimed_speaker,
+            leds: unclaimed_leds,
+            i2c1: peripherals.i2c1,
+            uart1: peripherals.uart1,
+            spi3: peripherals.spi3,
+            adc2: peripherals.adc2,
+            modem: peripherals.modem,
+            ledc_channels: (
+                peripherals.ledc.channel1,
+                peripherals.ledc.channel2,
+                peripherals.ledc.channel3,
+            ),
+            ledc_timers: (
+                peripherals.ledc.timer1,
+                peripherals.ledc.timer2,
+                peripherals.ledc.timer3,
+            ),
+            gpio0: peripherals.pins.gpio0,
+            gpio2: peripherals.pins.gpio2,
+            gpio4: peripherals.pins.gpio4,
+            gpio5: peripherals.pins.gpio5,
+            gpio12: peripherals.pins.gpio12,
+            gpio13: peripherals.pins.gpio13,
+            gpio19: peripherals.pins.gpio19,
+            gpio34: peripherals.pins.gpio34,
+            gpio35: peripherals.pins.gpio35,
+        };
+
+        Ok((m5, unclaimed))
+    }
+}
@@ -0,0 +1,352 @@
+//! Button edges pushed from the GPIO interrupts into a bounded lock-free queue.
+//!
+//! The interrupt handlers only push to the queue and give a semaphore, which is safe from an ISR,
+//! and the application takes the edges from any task without `unsafe` code nor global statics.
+//! A task waiting for an edge sleeps until the interrupt wakes it:
+//!
+//! ```ignore
+//! let mut queue = m5.button_queue(16)?;
+//! loop {
+//!     if let Some(edge) = queue.recv_timeout(Duration::from_secs(1)) {
+//!         let events = m5.buttons.update(queue.levels(), edge.at);
+//!     }
+//! }
+//! ```
+
+#[cfg(not(feature = "esp"))]
+use std::sync::{Condvar, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    hal::{ButtonInterrupt, Platform},
+    power::Button,
+    M5Go, M5GoError,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonEdge {
+    pub button: Button,
+    /// Whether the button went down
+    pub pressed: bool,
+    /// When the interrupt fired
+    pub at: Instant,
+}
+
+struct Slot {
+    /// Position the slot is ready for, see `Ring::push` and `Ring::pop`
+    sequence: AtomicUsize,
+    /// The button index, plus 4 when it was pressed
+    edge: AtomicU32,
+    at_ms: AtomicU32,
+}
+
+/// A bounded multi-producer multi-consumer queue, after Dmitry Vyukov's. Neither side blocks
+/// nor allocates, a consumer may wait on the signal given after each push.
+struct Ring {
+    slots: Box<[Slot]>,
+    signal: Signal,
+    mask: usize,
+    enqueue: AtomicUsize,
+    dequeue: AtomicUsize,
+    dropped: AtomicU32,
+    #[cfg(not(feature = "esp"))]
+    origin: Instant,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Result<Self, M5GoError> {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|index| Slot {
+                sequence: AtomicUsize::new(index),
+                edge: AtomicU32::new(0),
+                at_ms: AtomicU32::new(0),
+            })
+            .collect();
+        Ok(Self {
+            slots,
+            signal: Signal::new()?,
+            mask: capacity - 1,
+            enqueue: AtomicUsize::new(0),
+            dequeue: AtomicUsize::new(0),
+            dropped: AtomicU32::new(0),
+            #[cfg(not(feature = "esp"))]
+            origin: Instant::now(),
+        })
+    }
+
+    /// Called from the interrupt handlers, the edge is dropped when the queue is full
+    fn push(&self, button: Button, pressed: bool) {
+        let edge = button as u32 + if pressed { 4 } else { 0 };
+        let at_ms = self.now_ms();
+
+        let mut position = self.enqueue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence as isize).wrapping_sub(position as isize) {
+                0 => {
+                    match self.enqueue.compare_exchange_weak(
+                        position,
+                        position.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            slot.edge.store(edge, Ordering::Relaxed);
+                            slot.at_ms.store(at_ms, Ordering::Relaxed);
+                            slot.sequence
+                                .store(position.wrapping_add(1), Ordering::Release);
+                            self.signal.give();
+                            return;
+                        }
+                        Err(current) => position = current,
+                    }
+                }
+                // The consumer has not freed the slot yet, the queue is full
+                difference if difference < 0 => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                _ => position = self.enqueue.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<(u32, u32)> {
+        let mut position = self.dequeue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence as isize).wrapping_sub(position.wrapping_add(1) as isize) {
+                0 => {
+                    match self.dequeue.compare_exchange_weak(
+                        position,
+                        position.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            let edge = slot.edge.load(Ordering::Relaxed);
+                            let at_ms = slot.at_ms.load(Ordering::Relaxed);
+                            slot.sequence
+                                .store(position.wrapping_add(self.mask + 1), Ordering::Release);
+                            return Some((edge, at_ms));
+                        }
+                        Err(current) => position = current,
+                    }
+                }
+                // Nothing pushed there yet, the queue is empty
+                difference if difference < 0 => return None,
+                _ => position = self.dequeue.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Milliseconds on a clock that can be read from an ISR, it wraps after 49 days
+    #[cfg(feature = "esp")]
+    fn now_ms(&self) -> u32 {
+        (unsafe { esp_idf_sys::esp_timer_get_time() } / 1000) as u32
+    }
+
+    #[cfg(not(feature = "esp"))]
+    fn now_ms(&self) -> u32 {
+        self.origin.elapsed().as_millis() as u32
+    }
+}
+
+/// Wakes the consumer waiting for an edge, a FreeRTOS binary semaphore given from the ISR
+#[cfg(feature = "esp")]
+struct Signal(esp_idf_sys::QueueHandle_t);
+
+// SAFETY: FreeRTOS semaphores are meant to be shared between tasks and interrupts
+#[cfg(feature = "esp")]
+unsafe impl Send for Signal {}
+#[cfg(feature = "esp")]
+unsafe impl Sync for Signal {}
+
+#[cfg(feature = "esp")]
+impl Signal {
+    /// `queueQUEUE_TYPE_BINARY_SEMAPHORE`, not available in the esp-idf-sys bindings
+    const BINARY_SEMAPHORE: u8 = 3;
+
+    fn new() -> Result<Self, M5GoError> {
+        let semaphore = unsafe { esp_idf_sys::xQueueGenericCreate(1, 0, Self::BINARY_SEMAPHORE) };
+        if semaphore.is_null() {
+            return Err(M5GoError::gpio(
+                "Unable to create the button queue semaphore",
+                "out of memory",
+            ));
+        }
+        Ok(Self(semaphore))
+    }
+
+    /// Called from the interrupt handlers
+    fn give(&self) {
+        let mut woken = 0;
+        unsafe { esp_idf_sys::xQueueGiveFromISR(self.0, &mut woken) };
+        if woken != 0 {
+            esp_idf_hal::task::do_yield();
+        }
+    }
+
+    /// Returns whether it was given before the timeout, `None` waits forever
+    fn take(&self, timeout: Option<Duration>) -> bool {
+        let ticks = esp_idf_hal::delay::TickType::from(timeout).0;
+        unsafe { esp_idf_sys::xQueueSemaphoreTake(self.0, ticks) != 0 }
+    }
+}
+
+#[cfg(feature = "esp")]
+impl Drop for Signal {
+    fn drop(&mut self) {
+        unsafe { esp_idf_sys::vQueueDelete(self.0) };
+    }
+}
+
+/// Wakes the consumer waiting for an edge, the simulated buttons push from a regular thread
+#[cfg(not(feature = "esp"))]
+#[derive(Default)]
+struct Signal {
+    given: Mutex<bool>,
+    condvar: Condvar,
+}
+
+#[cfg(not(feature = "esp"))]
+impl Signal {
+    fn new() -> Result<Self, M5GoError> {
+        Ok(Self::default())
+    }
+
+    fn give(&self) {
+        *self.given.lock().unwrap_or_else(|p| p.into_inner()) = true;
+        self.condvar.notify_one();
+    }
+
+    /// Returns whether it was given before the timeout, `None` waits forever
+    fn take(&self, timeout: Option<Duration>) -> bool {
+        let given = self.given.lock().unwrap_or_else(|p| p.into_inner());
+        let mut given = match timeout {
+            Some(timeout) => {
+                self.condvar
+                    .wait_timeout_while(given, timeout, |given| !*given)
+                    .unwrap_or_else(|p| p.into_inner())
+                    .0
+            }
+            None => self
+                .condvar
+                .wait_while(given, |given| !*given)
+                .unwrap_or_else(|p| p.into_inner()),
+        };
+        std::mem::replace(&mut *given, false)
+    }
+}
+
+/// The consuming end of the queue
+pub struct ButtonQueue {
+    ring: Arc<Ring>,
+    levels: [bool; 3],
+}
+
+impl ButtonQueue {
+    /// The next edge if there is one
+    pub fn try_recv(&mut self) -> Option<ButtonEdge> {
+        let (edge, at_ms) = self.ring.pop()?;
+        let button = match edge & 3 {
+            0 => Button::A,
+            1 => Button::B,
+            _ => Button::C,
+        };
+        let pressed = edge & 4 != 0;
+        self.levels[button as usize] = pressed;
+
+        let age = self.ring.now_ms().wrapping_sub(at_ms);
+        let now = Instant::now();
+        let at = now
+            .checked_sub(Duration::from_millis(age as u64))
+            .unwrap_or(now);
+
+        Some(ButtonEdge {
+            button,
+            pressed,
+            at,
+        })
+    }
+
+    /// Wait for the next edge
+    pub fn recv(&mut self) -> ButtonEdge {
+        loop {
+            if let Some(edge) = self.try_recv() {
+                return edge;
+            }
+            self.ring.signal.take(None);
+        }
+    }
+
+    /// Wait for the next edge, `None` when none came in time
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<ButtonEdge> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(edge) = self.try_recv() {
+                return Some(edge);
+            }
+            // The signal may be left from an edge already received, it is then taken right away
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || !self.ring.signal.take(Some(left)) {
+                return self.try_recv();
+            }
+        }
+    }
+
+    /// Levels of A, B and C after the edges received so far, for
+    /// [`Buttons::update`](crate::buttons::Buttons::update)
+    pub fn levels(&self) -> [bool; 3] {
+        self.levels
+    }
+
+    /// Edges lost because the queue was full
+    pub fn dropped(&self) -> u32 {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<P: Platform> M5Go<P>
+where
+    P::ButtonA: ButtonInterrupt,
+    P::ButtonB: ButtonInterrupt,
+    P::ButtonC: ButtonInterrupt,
+{
+    /// Queue the presses and releases of the buttons from their interrupts, keeping `capacity`
+    /// edges at most, rounded up to a power of two. A new queue replaces the previous one.
+    pub fn button_queue(&mut self, capacity: usize) -> Result<ButtonQueue, M5GoError> {
+        let ring = Arc::new(Ring::new(capacity)?);
+        let levels = self.buttons_pressed();
+
+        let (a, b, c) = (Arc::clone(&ring), Arc::clone(&ring), Arc::clone(&ring));
+        // SAFETY: the callbacks only push to the ring and give the semaphore, neither blocks nor
+        // allocates
+        unsafe {
+            self.button_a
+                .subscribe_edges(move |pressed| a.push(Button::A, pressed))?;
+            self.button_b
+                .subscribe_edges(move |pressed| b.push(Button::B, pressed))?;
+            self.button_c
+                .subscribe_edges(move |pressed| c.push(Button::C, pressed))?;
+        }
+
+        Ok(ButtonQueue { ring, levels })
+    }
+
+    /// Stop queueing the edges
+    pub fn stop_button_queue(&mut self) -> Result<(), M5GoError> {
+        self.button_a.unsubscribe_edges()?;
+        self.button_b.unsubscribe_edges()?;
+        self.button_c.unsubscribe_edges()
+    }
+}
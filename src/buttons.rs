@@ -0,0 +1,666 @@
+//! Debounced front buttons, turning the raw levels into presses, clicks, double clicks, long
+//! presses and auto-repeats.
+//!
+//! [`Buttons`] only sees the levels and the time they were read at, so its timing can be checked
+//! on the host by feeding it made-up instants:
+//!
+//! ```ignore
+//! let mut buttons = Buttons::new(ButtonConfig::new());
+//! let start = Instant::now();
+//! buttons.update([true, false, false], start);
+//! let events = buttons.update([true, false, false], start + Duration::from_millis(30));
+//! assert_eq!(events, [ButtonEvent::Pressed(Button::A)]);
+//! ```
+//!
+//! Chords are buttons held together, or one button held long, registered under a name so that
+//! every firmware handles them the same way. A boot chord only counts when its buttons were
+//! already held when the buttons were first read:
+//!
+//! ```ignore
+//! m5.buttons.add_chord("settings", Chord::new(&[Button::A, Button::C]));
+//! m5.buttons.add_chord("factory reset", Chord::new(&[Button::B]).hold(Duration::from_secs(3)).at_boot());
+//! if m5.check_boot_chords() == Some("factory reset") {
+//!     reset_settings()?;
+//! }
+//! ```
+//!
+//! [`M5Go::run`](crate::M5Go::run) feeds the buttons of the device and dispatches their events.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    hal::{ButtonInput, Platform},
+    power::Button,
+    M5Go,
+};
+
+/// How often [`M5Go::check_boot_chords`] reads the buttons
+const BOOT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonConfig {
+    /// How long a level must hold before it is believed
+    pub debounce: Duration,
+    /// Longest gap between two clicks making a double click, `None` to report every click right
+    /// away
+    pub double_click: Option<Duration>,
+    /// How long a button must be held for a long press
+    pub long_press: Duration,
+    /// Delay before the first repeat then period of the repeats while a button is held, `None`
+    /// to disable them
+    pub repeat: Option<(Duration, Duration)>,
+}
+
+impl ButtonConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn double_click(mut self, double_click: Option<Duration>) -> Self {
+        self.double_click = double_click;
+        self
+    }
+
+    pub fn long_press(mut self, long_press: Duration) -> Self {
+        self.long_press = long_press;
+        self
+    }
+
+    pub fn repeat(mut self, delay: Duration, period: Duration) -> Self {
+        self.repeat = Some((delay, period));
+        self
+    }
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(20),
+            double_click: Some(Duration::from_millis(300)),
+            long_press: Duration::from_millis(800),
+            repeat: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Pressed(Button),
+    Released(Button),
+    /// A short press, reported once the double click delay is over
+    Click(Button),
+    DoubleClick(Button),
+    /// The button has been held for the long press duration, no click follows the release
+    LongPress(Button, Duration),
+    /// The button is still held, no click follows the release
+    Repeat(Button),
+    /// A registered chord was held long enough, none of its buttons clicks on release
+    Chord(&'static str),
+}
+
+/// Buttons held together, exactly, for some time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    buttons: [bool; 3],
+    /// Counted from the last of the buttons going down
+    pub hold: Duration,
+    /// Only when the buttons were held at boot
+    pub at_boot: bool,
+}
+
+impl Chord {
+    pub fn new(buttons: &[Button]) -> Self {
+        let mut held = [false; 3];
+        for button in buttons {
+            held[*button as usize] = true;
+        }
+        Self {
+            buttons: held,
+            hold: Duration::from_secs(1),
+            at_boot: false,
+        }
+    }
+
+    pub fn hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    pub fn at_boot(mut self) -> Self {
+        self.at_boot = true;
+        self
+    }
+
+    pub fn buttons(&self) -> impl Iterator<Item = Button> + '_ {
+        [Button::A, Button::B, Button::C]
+            .into_iter()
+            .filter(|button| self.buttons[*button as usize])
+    }
+}
+
+struct RegisteredChord {
+    name: &'static str,
+    chord: Chord,
+    /// Reported for the current hold
+    fired: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tracker {
+    /// Last level read and since when
+    raw: bool,
+    raw_since: Instant,
+    /// Debounced level
+    pressed: bool,
+    pressed_at: Instant,
+    /// A long press or a repeat happened, the release is not a click
+    held: bool,
+    next_repeat: Option<Instant>,
+    /// A click waiting to become a double click, until the deadline
+    pending_click: Option<Instant>,
+    /// Held since the first update, for the boot chords
+    since_boot: bool,
+}
+
+impl Tracker {
+    fn new(now: Instant) -> Self {
+        Self {
+            raw: false,
+            raw_since: now,
+            pressed: false,
+            pressed_at: now,
+            held: false,
+            next_repeat: None,
+            pending_click: None,
+            since_boot: false,
+        }
+    }
+
+    fn debounce(
+        &mut self,
+        button: Button,
+        raw: bool,
+        now: Instant,
+        config: &ButtonConfig,
+        events: &mut Vec<ButtonEvent>,
+    ) {
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now;
+            if !raw {
+                self.since_boot = false;
+            }
+        }
+
+        if self.raw != self.pressed && now.duration_since(self.raw_since) >= config.debounce {
+            self.pressed = self.raw;
+            if self.pressed {
+                self.press(button, config, events);
+            } else {
+                self.release(button, now, config, events);
+            }
+        }
+    }
+
+    /// `chorded` when other buttons are held too, the press is then part of a chord rather than
+    /// a long press or repeats
+    fn time(
+        &mut self,
+        button: Button,
+        now: Instant,
+        config: &ButtonConfig,
+        chorded: bool,
+        events: &mut Vec<ButtonEvent>,
+    ) {
+        if self.pressed && chorded {
+            if !self.held {
+                self.hold(button, events);
+            }
+        } else if self.pressed {
+            let held_for = now.duration_since(self.pressed_at);
+            if !self.held && held_for >= config.long_press {
+                self.hold(button, events);
+                events.push(ButtonEvent::LongPress(button, held_for));
+            }
+            if let (Some(next), Some((_, period))) = (self.next_repeat, config.repeat) {
+                if now >= next {
+                    self.hold(button, events);
+                    events.push(ButtonEvent::Repeat(button));
+                    // Skip the repeats missed by a late update, keeping their pace
+                    let behind = now.duration_since(next).as_nanos() % period.as_nanos().max(1);
+                    self.next_repeat = Some(now + period - Duration::from_nanos(behind as u64));
+                }
+            }
+        } else if matches!(self.pending_click, Some(deadline) if now >= deadline) {
+            self.pending_click = None;
+            events.push(ButtonEvent::Click(button));
+        }
+    }
+
+    fn press(&mut self, button: Button, config: &ButtonConfig, events: &mut Vec<ButtonEvent>) {
+        // The level changed when it was first read, not once it settled
+        self.pressed_at = self.raw_since;
+        self.held = false;
+        self.next_repeat = config.repeat.map(|(delay, _)| self.pressed_at + delay);
+        events.push(ButtonEvent::Pressed(button));
+    }
+
+    fn release(
+        &mut self,
+        button: Button,
+        now: Instant,
+        config: &ButtonConfig,
+        events: &mut Vec<ButtonEvent>,
+    ) {
+        self.next_repeat = None;
+        events.push(ButtonEvent::Released(button));
+        if self.held {
+            return;
+        }
+
+        match (self.pending_click.take(), config.double_click) {
+            (Some(_), _) => events.push(ButtonEvent::DoubleClick(button)),
+            (None, Some(delay)) => self.pending_click = Some(now + delay),
+            (None, None) => events.push(ButtonEvent::Click(button)),
+        }
+    }
+
+    /// The press is not a click, a click waiting for this press to be a double click is one
+    fn hold(&mut self, button: Button, events: &mut Vec<ButtonEvent>) {
+        self.held = true;
+        if self.pending_click.take().is_some() {
+            events.push(ButtonEvent::Click(button));
+        }
+    }
+}
+
+pub struct Buttons {
+    config: ButtonConfig,
+    trackers: [Tracker; 3],
+    chords: Vec<RegisteredChord>,
+    /// The first update is the boot state
+    started: bool,
+}
+
+impl Buttons {
+    pub fn new(config: ButtonConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            trackers: [Tracker::new(now); 3],
+            chords: Vec::new(),
+            started: false,
+        }
+    }
+
+    pub fn config(&self) -> &ButtonConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ButtonConfig) {
+        self.config = config;
+    }
+
+    /// Report [`ButtonEvent::Chord`] with `name` when the chord is held, replacing the chord
+    /// registered under the same name
+    pub fn add_chord(&mut self, name: &'static str, chord: Chord) {
+        self.remove_chord(name);
+        self.chords.push(RegisteredChord {
+            name,
+            chord,
+            fired: false,
+        });
+    }
+
+    /// Returns whether a chord was registered under this name
+    pub fn remove_chord(&mut self, name: &str) -> bool {
+        let count = self.chords.len();
+        self.chords.retain(|registered| registered.name != name);
+        self.chords.len() != count
+    }
+
+    /// Feed the levels of A, B and C read at `now`, call it at least every few milliseconds
+    pub fn update(&mut self, pressed: [bool; 3], now: Instant) -> Vec<ButtonEvent> {
+        if !self.started {
+            self.started = true;
+            for (tracker, raw) in self.trackers.iter_mut().zip(pressed) {
+                tracker.since_boot = raw;
+            }
+        }
+
+        let mut events = Vec::new();
+        for ((button, tracker), raw) in BUTTONS.into_iter().zip(&mut self.trackers).zip(pressed) {
+            tracker.debounce(button, raw, now, &self.config, &mut events);
+        }
+
+        let chorded = self
+            .trackers
+            .iter()
+            .filter(|tracker| tracker.pressed)
+            .count()
+            > 1;
+        for (button, tracker) in BUTTONS.into_iter().zip(&mut self.trackers) {
+            tracker.time(button, now, &self.config, chorded, &mut events);
+        }
+
+        self.update_chords(now, &mut events);
+        events
+    }
+
+    fn update_chords(&mut self, now: Instant, events: &mut Vec<ButtonEvent>) {
+        let held = self.trackers.map(|tracker| tracker.pressed);
+        for registered in &mut self.chords {
+            let chord = registered.chord;
+            let trackers = &mut self.trackers;
+            let matching = held == chord.buttons
+                && (!chord.at_boot
+                    || chord
+                        .buttons()
+                        .all(|button| trackers[button as usize].since_boot));
+            if !matching {
+                registered.fired = false;
+                continue;
+            }
+            if registered.fired {
+                continue;
+            }
+
+            let since = chord
+                .buttons()
+                .map(|button| trackers[button as usize].pressed_at)
+                .max();
+            if matches!(since, Some(since) if now.duration_since(since) >= chord.hold) {
+                registered.fired = true;
+                events.push(ButtonEvent::Chord(registered.name));
+                for button in chord.buttons() {
+                    let tracker = &mut trackers[button as usize];
+                    if !tracker.held {
+                        tracker.hold(button, events);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a boot chord may still be reported, its buttons being held since the first
+    /// update
+    pub fn boot_chord_pending(&self) -> bool {
+        if !self.started {
+            return self
+                .chords
+                .iter()
+                .any(|registered| registered.chord.at_boot);
+        }
+        self.chords.iter().any(|registered| {
+            registered.chord.at_boot
+                && !registered.fired
+                && registered
+                    .chord
+                    .buttons()
+                    .all(|button| self.trackers[button as usize].since_boot)
+        })
+    }
+
+    /// The debounced level
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.trackers[button as usize].pressed
+    }
+
+    /// How long the button has been held, `None` when it is released
+    pub fn held_for(&self, button: Button, now: Instant) -> Option<Duration> {
+        let tracker = &self.trackers[button as usize];
+        tracker
+            .pressed
+            .then(|| now.duration_since(tracker.pressed_at))
+    }
+}
+
+const BUTTONS: [Button; 3] = [Button::A, Button::B, Button::C];
+
+impl Default for Buttons {
+    fn default() -> Self {
+        Self::new(ButtonConfig::default())
+    }
+}
+
+impl<P: Platform> M5Go<P> {
+    /// Read the buttons and update [`M5Go::buttons`], when not using [`M5Go::run`]
+    pub fn poll_buttons(&mut self) -> Vec<ButtonEvent> {
+        let pressed = self.buttons_pressed();
+        self.buttons.update(pressed, Instant::now())
+    }
+
+    /// Poll the buttons held at boot until one of the boot chords is reported, returning its
+    /// name, or until they are released. Call it right after creating the device, the other
+    /// button events are dropped meanwhile.
+    pub fn check_boot_chords(&mut self) -> Option<&'static str> {
+        while self.buttons.boot_chord_pending() {
+            let chord = self
+                .poll_buttons()
+                .into_iter()
+                .find_map(|event| match event {
+                    ButtonEvent::Chord(name) => Some(name),
+                    _ => None,
+                });
+            if chord.is_some() {
+                return chord;
+            }
+            thread::sleep(BOOT_POLL_INTERVAL);
+        }
+        None
+    }
+
+    /// The raw levels of A, B and C
+    pub(crate) fn buttons_pressed(&self) -> [bool; 3] {
+        [
+            self.button_a.is_pressed(),
+            self.button_b.is_pressed(),
+            self.button_c.is_pressed(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [bool; 3] = [true, false, false];
+    const NONE: [bool; 3] = [false; 3];
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Feed the levels at each offset from `start`, returning every event with its offset
+    fn feed(
+        buttons: &mut Buttons,
+        start: Instant,
+        steps: &[(u64, [bool; 3])],
+    ) -> Vec<(u64, ButtonEvent)> {
+        steps
+            .iter()
+            .flat_map(|(at, pressed)| {
+                buttons
+                    .update(*pressed, start + ms(*at))
+                    .into_iter()
+                    .map(move |event| (*at, event))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn debounce() {
+        let mut buttons = Buttons::default();
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[(0, NONE), (10, A), (15, NONE), (20, A), (30, A), (40, A)],
+        );
+        assert_eq!(events, [(40, ButtonEvent::Pressed(Button::A))]);
+        assert!(buttons.is_pressed(Button::A));
+        assert_eq!(buttons.held_for(Button::A, start + ms(50)), Some(ms(30)));
+    }
+
+    #[test]
+    fn click() {
+        let mut buttons = Buttons::default();
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[
+                (0, A),
+                (20, A),
+                (100, NONE),
+                (120, NONE),
+                (400, NONE),
+                (420, NONE),
+            ],
+        );
+        assert_eq!(
+            events,
+            [
+                (20, ButtonEvent::Pressed(Button::A)),
+                (120, ButtonEvent::Released(Button::A)),
+                (420, ButtonEvent::Click(Button::A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn click_without_double_click() {
+        let mut buttons = Buttons::new(ButtonConfig::new().double_click(None));
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[(0, A), (20, A), (100, NONE), (120, NONE)],
+        );
+        assert_eq!(
+            events,
+            [
+                (20, ButtonEvent::Pressed(Button::A)),
+                (120, ButtonEvent::Released(Button::A)),
+                (120, ButtonEvent::Click(Button::A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_click() {
+        let mut buttons = Buttons::default();
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[
+                (0, A),
+                (20, A),
+                (100, NONE),
+                (120, NONE),
+                (200, A),
+                (220, A),
+                (300, NONE),
+                (320, NONE),
+                (1000, NONE),
+            ],
+        );
+        assert_eq!(
+            events,
+            [
+                (20, ButtonEvent::Pressed(Button::A)),
+                (120, ButtonEvent::Released(Button::A)),
+                (220, ButtonEvent::Pressed(Button::A)),
+                (320, ButtonEvent::Released(Button::A)),
+                (320, ButtonEvent::DoubleClick(Button::A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let mut buttons = Buttons::default();
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[
+                (0, A),
+                (20, A),
+                (790, A),
+                (810, A),
+                (900, NONE),
+                (920, NONE),
+                (1500, NONE),
+            ],
+        );
+        assert_eq!(
+            events,
+            [
+                (20, ButtonEvent::Pressed(Button::A)),
+                (810, ButtonEvent::LongPress(Button::A, ms(810))),
+                (920, ButtonEvent::Released(Button::A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        let mut buttons = Buttons::new(ButtonConfig::new().repeat(ms(500), ms(100)));
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[
+                (0, A),
+                (20, A),
+                (499, A),
+                (500, A),
+                (550, A),
+                (600, A),
+                (700, A),
+                (701, NONE),
+                (721, NONE),
+                (1500, NONE),
+            ],
+        );
+        assert_eq!(
+            events,
+            [
+                (20, ButtonEvent::Pressed(Button::A)),
+                (500, ButtonEvent::Repeat(Button::A)),
+                (600, ButtonEvent::Repeat(Button::A)),
+                (700, ButtonEvent::Repeat(Button::A)),
+                (721, ButtonEvent::Released(Button::A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn late_update_skips_the_missed_repeats() {
+        let mut buttons = Buttons::new(ButtonConfig::new().repeat(ms(500), ms(100)));
+        let start = Instant::now();
+        let events = feed(
+            &mut buttons,
+            start,
+            &[(0, A), (20, A), (750, A), (760, A), (799, A), (800, A)],
+        );
+        assert_eq!(
+            events,
+            [
+                (20, ButtonEvent::Pressed(Button::A)),
+                (750, ButtonEvent::Repeat(Button::A)),
+                (800, ButtonEvent::Repeat(Button::A)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! A [`log`] backend printing the records on the screen, for when no serial cable is at hand.
+//!
+//! Records can be logged from any task, so they are only queued by the logger. The task owning
+//! the screen draws them with [`LogConsole::render`], on every [`Event::Tick`](crate::event::Event)
+//! for example.
+
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoFont, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::{Point, Primitive, RgbColor, Size},
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+    Drawable,
+};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{crash, hal::Display, screen::Screen, M5GoError};
+
+#[derive(Clone, Copy)]
+pub struct ConsoleConfig {
+    /// Part of the screen the console scrolls in
+    pub region: Rectangle,
+    pub font: &'static MonoFont<'static>,
+    pub background: Rgb565,
+    /// Text colors from [`Level::Error`] to [`Level::Trace`]
+    pub colors: [Rgb565; 5],
+    /// Also send the records to the serial logger
+    pub serial: bool,
+    pub level: LevelFilter,
+}
+
+impl ConsoleConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn region(mut self, region: Rectangle) -> Self {
+        self.region = region;
+        self
+    }
+
+    pub fn font(mut self, font: &'static MonoFont<'static>) -> Self {
+        self.font = font;
+        self
+    }
+
+    pub fn background(mut self, background: Rgb565) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn color(mut self, level: Level, color: Rgb565) -> Self {
+        self.colors[level as usize - 1] = color;
+        self
+    }
+
+    pub fn serial(mut self, serial: bool) -> Self {
+        self.serial = serial;
+        self
+    }
+
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    fn columns(&self) -> usize {
+        let width = self.font.character_size.width + self.font.character_spacing;
+        (self.region.size.width / width).max(1) as usize
+    }
+
+    fn rows(&self) -> usize {
+        (self.region.size.height / self.font.character_size.height) as usize
+    }
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            region: Rectangle::new(Point::zero(), Size::new(320, 240)),
+            font: &FONT_6X10,
+            background: Rgb565::BLACK,
+            colors: [
+                Rgb565::RED,
+                Rgb565::YELLOW,
+                Rgb565::WHITE,
+                Rgb565::CYAN,
+                Rgb565::new(16, 32, 16),
+            ],
+            serial: true,
+            level: LevelFilter::Info,
+        }
+    }
+}
+
+struct Lines {
+    lines: VecDeque<(Level, String)>,
+    columns: usize,
+    rows: usize,
+    /// Whether lines were added since the last render
+    dirty: bool,
+}
+
+impl Lines {
+    /// Long lines are wrapped and the oldest ones scroll out
+    fn push(&mut self, level: Level, text: &str) {
+        for line in text.lines() {
+            let characters = line.chars().collect::<Vec<_>>();
+            for chunk in characters.chunks(self.columns) {
+                self.lines.push_back((level, chunk.iter().collect()));
+            }
+        }
+        while self.lines.len() > self.rows {
+            self.lines.pop_front();
+        }
+        self.dirty = true;
+    }
+}
+
+/// Cloning the console gives another handle on the same lines
+#[derive(Clone)]
+pub struct LogConsole {
+    config: ConsoleConfig,
+    lines: Arc<Mutex<Lines>>,
+}
+
+impl LogConsole {
+    pub fn new(config: ConsoleConfig) -> Self {
+        let lines = Lines {
+            lines: VecDeque::new(),
+            columns: config.columns(),
+            rows: config.rows(),
+            dirty: false,
+        };
+        Self {
+            config,
+            lines: Arc::new(Mutex::new(lines)),
+        }
+    }
+
+    /// Make the console the global logger, which can only be set once
+    pub fn install(&self) -> Result<(), M5GoError> {
+        let logger = ConsoleLogger {
+            lines: Arc::clone(&self.lines),
+            level: self.config.level,
+            serial: self.config.serial,
+        };
+        log::set_logger(Box::leak(Box::new(logger)))
+            .map_err(|e| M5GoError::screen("Unable to install the console", e))?;
+        log::set_max_level(self.config.level);
+        Ok(())
+    }
+
+    /// Queue a line without going through the logger
+    pub fn print(&self, level: Level, text: &str) {
+        if let Ok(mut lines) = self.lines.lock() {
+            lines.push(level, text);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut lines) = self.lines.lock() {
+            lines.lines.clear();
+            lines.dirty = true;
+        }
+    }
+
+    /// Draw the lines if some were added since the last call, returns whether it drew
+    pub fn render<D: Display>(&self, screen: &mut Screen<D>) -> Result<bool, M5GoError>
+    where
+        D::Error: Debug,
+    {
+        let lines = match self.lines.lock() {
+            Ok(mut lines) if lines.dirty => {
+                lines.dirty = false;
+                lines.lines.clone()
+            }
+            _ => return Ok(false),
+        };
+
+        let region = self.config.region;
+        region
+            .into_styled(PrimitiveStyle::with_fill(self.config.background))
+            .draw(&mut *screen.driver())
+            .map_err(|e| M5GoError::screen("Unable to clear the console", e))?;
+
+        let height = self.config.font.character_size.height as i32;
+        for (row, (level, line)) in lines.iter().enumerate() {
+            let style =
+                MonoTextStyle::new(self.config.font, self.config.colors[*level as usize - 1]);
+            let position = region.top_left + Point::new(0, row as i32 * height);
+            Text::with_baseline(line, position, style, Baseline::Top)
+                .draw(&mut *screen.driver())
+                .map_err(|e| M5GoError::screen("Unable to draw the console", e))?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// The part of the console given to [`log`], which needs it to be `Sync` unlike the font
+struct ConsoleLogger {
+    lines: Arc<Mutex<Lines>>,
+    level: LevelFilter,
+    serial: bool,
+}
+
+impl ConsoleLogger {
+    fn forward(&self, record: &Record) {
+        #[cfg(feature = "esp")]
+        esp_idf_svc::log::EspLogger.log(record);
+
+        #[cfg(not(feature = "esp"))]
+        eprintln!(
+            "{:<5} {}: {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let prefix = match record.level() {
+            Level::Error => 'E',
+            Level::Warn => 'W',
+            Level::Info => 'I',
+            Level::Debug => 'D',
+            Level::Trace => 'T',
+        };
+        let line = format!("{} {}", prefix, record.args());
+        crash::record_log(&line);
+        if let Ok(mut lines) = self.lines.lock() {
+            lines.push(record.level(), &line);
+        }
+
+        if self.serial {
+            self.forward(record);
+        }
+    }
+
+    fn flush(&self) {}
+}
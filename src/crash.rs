@@ -0,0 +1,348 @@
+//! Panic screen, red led bars and crash reports kept across the reboot.
+//!
+//! The panic hook stores the panic, the uptime and the last log lines. On the next boot,
+//! [`M5Go::crash_report`] gathers them with the reset reason, so that they can be read over
+//! BLE with `BleConfig::crash_report`, or on Port C with the [`UART_COMMAND`]. The report stays
+//! until [`M5Go::clear_crash_report`], once a BLE client read it whole or on the
+//! [`CLEAR_COMMAND`], so that a reboot before anyone reads it does not lose it.
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    fmt::Debug,
+    panic::Location,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::{DrawTarget, Point, RgbColor},
+    text::{Alignment, Text},
+    Drawable,
+};
+use smart_leds::colors::RED;
+
+use crate::{
+    hal::{Display, LedStrip, Platform, Scheduler, SerialPort, Storage, ToneOutput, MAX_VALUE_LEN},
+    power::ResetReason,
+    screen::Screen,
+    settings::SettingValue,
+    M5Go, M5GoError,
+};
+
+const PANIC_KEY: &str = "last_panic";
+const UPTIME_KEY: &str = "crash_uptime";
+const LOG_KEY: &str = "crash_log";
+const LOG_TAIL_LEN: usize = 20;
+/// Characters of `FONT_10X20` fitting on a line
+const COLUMNS: usize = 32;
+
+/// Line received on Port C to ask for the crash report
+pub const UART_COMMAND: &str = "crash";
+/// Line received on Port C, or value written to the BLE characteristic, to clear the crash
+/// report
+pub const CLEAR_COMMAND: &str = "crash clear";
+
+static LOG_TAIL: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+/// The reset reason holds for the whole run, it is reported until the report is cleared
+static RESET_CLEARED: AtomicBool = AtomicBool::new(false);
+
+/// Keep a line for the crash report, the [`LogConsole`](crate::console::LogConsole) does it for
+/// every record
+pub fn record_log(line: &str) {
+    if let Ok(mut tail) = LOG_TAIL.lock() {
+        tail.push_back(line.to_string());
+        while tail.len() > LOG_TAIL_LEN {
+            tail.pop_front();
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicReport {
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: String,
+}
+
+impl PanicReport {
+    fn new(payload: &(dyn Any + Send), location: Option<&Location>) -> Self {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Box<dyn Any>".to_string()
+        };
+        let location = location
+            .map(|location| location.to_string())
+            .unwrap_or_else(|| "unknown location".to_string());
+        Self { message, location }
+    }
+
+    /// Cut to what the storage keeps, the location comes first so it is never lost
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut text = format!("{}\n{}", self.location, self.message);
+        let mut end = text.len().min(MAX_VALUE_LEN);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.into_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(bytes);
+        let (location, message) = text.split_once('\n')?;
+        Some(Self {
+            message: message.to_string(),
+            location: location.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked at {}: {}", self.location, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrashReport {
+    pub reset_reason: ResetReason,
+    pub panic: Option<PanicReport>,
+    /// Time since boot when the panic happened
+    pub uptime: Option<Duration>,
+    /// Last log lines before the panic, oldest first
+    pub log_tail: Vec<String>,
+}
+
+impl CrashReport {
+    pub fn write_to<S: SerialPort>(&self, port: &mut S) -> Result<(), M5GoError> {
+        let text = self.to_string();
+        let mut bytes = text.as_bytes();
+        while !bytes.is_empty() {
+            let written = port.write_bytes(bytes)?;
+            if written == 0 {
+                return Err(M5GoError::uart(
+                    "Unable to write the crash report",
+                    bytes.len(),
+                ));
+            }
+            bytes = &bytes[written..];
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Reset reason: {:?}", self.reset_reason)?;
+        if let Some(uptime) = self.uptime {
+            writeln!(f, "Uptime: {:.3} s", uptime.as_secs_f32())?;
+        }
+        if let Some(panic) = &self.panic {
+            writeln!(f, "{}", panic)?;
+        }
+        if !self.log_tail.is_empty() {
+            writeln!(f, "Log:")?;
+            for line in &self.log_tail {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<P: Platform + 'static> M5Go<P>
+where
+    <P::Display as DrawTarget>::Error: Debug,
+{
+    /// Show the panics on the screen, turn the led bars red and store the report, which
+    /// [`M5Go::last_panic`] reads back after the reboot. The previous hook still runs afterwards.
+    ///
+    /// The hook keeps its own handles on the storage, the screen and the led bars. The screen
+    /// and the led bars are left alone when in use, by the panicking task or another one.
+    ///
+    /// ```ignore
+    /// let mut m5 = M5Go::new(peripherals)?.with_panic_hook();
+    /// ```
+    pub fn with_panic_hook(self) -> Self
+    where
+        P::Display: Send,
+        P::LedStrip: Send,
+        P::Storage: Clone + Send,
+        P::Scheduler: Clone + Send + Sync,
+    {
+        let screen = self.screen.as_ref().map(Screen::shared_driver);
+        let strip = self
+            .leds
+            .as_ref()
+            .map(|leds| (leds.shared_strip(), leds.len()));
+        let storage = Mutex::new(self.storage.clone());
+        let scheduler = self.scheduler.clone();
+
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let report = PanicReport::new(info.payload(), info.location());
+
+            // A failure here would panic again, which aborts right away
+            let mut storage = storage.lock().unwrap_or_else(|p| p.into_inner());
+            storage.write(PANIC_KEY, &report.to_bytes()).ok();
+            storage.write(UPTIME_KEY, &scheduler.now().to_bytes()).ok();
+            // The panic may have happened while logging
+            if let Ok(tail) = LOG_TAIL.try_lock() {
+                storage.write(LOG_KEY, &log_bytes(&tail)).ok();
+            }
+            drop(storage);
+
+            if let Some((strip, count)) = &strip {
+                if let Ok(mut strip) = strip.try_lock() {
+                    strip.write_pixels(&vec![RED; *count]).ok();
+                }
+            }
+            if let Some(Ok(mut display)) = screen.as_ref().map(|screen| screen.try_lock()) {
+                draw_panic(&mut *display, &report).ok();
+            }
+
+            previous(info);
+        }));
+
+        self
+    }
+
+    /// The panic that ended the previous run, if any
+    pub fn last_panic(&self) -> Result<Option<PanicReport>, M5GoError> {
+        Ok(self
+            .storage
+            .read(PANIC_KEY)?
+            .and_then(|bytes| PanicReport::from_bytes(&bytes)))
+    }
+
+    /// Returns whether there was a report
+    pub fn clear_last_panic(&mut self) -> Result<bool, M5GoError> {
+        self.storage.remove(PANIC_KEY)
+    }
+
+    /// What ended the previous run when it was not a regular reset, kept until
+    /// [`M5Go::clear_crash_report`]
+    ///
+    /// A part that cannot be read is left out of the report.
+    pub fn crash_report(&self) -> Result<Option<CrashReport>, M5GoError> {
+        let reset_reason = self.reset_reason();
+        let panic = self
+            .read_crash_part(PANIC_KEY)
+            .and_then(|bytes| PanicReport::from_bytes(&bytes));
+        let uptime = self
+            .read_crash_part(UPTIME_KEY)
+            .and_then(|bytes| Duration::from_bytes(&bytes));
+        let log_tail = self
+            .read_crash_part(LOG_KEY)
+            .map(|bytes| {
+                String::from_utf8_lossy(&bytes)
+                    .lines()
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let crashed = reset_reason.is_crash() && !RESET_CLEARED.load(Ordering::Relaxed);
+        if panic.is_none() && !crashed {
+            return Ok(None);
+        }
+
+        Ok(Some(CrashReport {
+            reset_reason,
+            panic,
+            uptime,
+            log_tail,
+        }))
+    }
+
+    /// Forget the crash report once it was read, the next [`M5Go::crash_report`] is `None`
+    /// until the next crash
+    pub fn clear_crash_report(&mut self) -> Result<(), M5GoError> {
+        for key in [PANIC_KEY, UPTIME_KEY, LOG_KEY] {
+            self.storage.remove(key)?;
+        }
+        RESET_CLEARED.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn read_crash_part(&self, key: &str) -> Option<Vec<u8>> {
+        self.storage.read(key).unwrap_or_else(|e| {
+            log::warn!("Unable to read the crash report {}: {:?}", key, e);
+            None
+        })
+    }
+
+    /// Draw the report in large text, the led bars turn red
+    pub fn show_panic(&mut self, report: &PanicReport) -> Result<(), M5GoError> {
+        if let Some(speaker) = &mut self.speaker {
+            speaker.stop()?;
+        }
+
+        if let Some(leds) = &mut self.leds {
+            leds.fill(RED);
+            leds.display()?;
+        }
+
+        match &self.screen {
+            Some(screen) => draw_panic(&mut *screen.driver(), report),
+            None => Ok(()),
+        }
+    }
+}
+
+fn draw_panic<D: Display>(display: &mut D, report: &PanicReport) -> Result<(), M5GoError>
+where
+    D::Error: Debug,
+{
+    display.set_backlight(true)?;
+    display
+        .clear(Rgb565::RED)
+        .map_err(|e| M5GoError::screen("Failed setting background", e))?;
+
+    let style = MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE);
+    let mut position = Point::new(5, 20);
+    for line in ["PANIC".to_string()]
+        .into_iter()
+        .chain(wrap(&report.location))
+        .chain(wrap(&report.message))
+    {
+        Text::with_alignment(&line, position, style, Alignment::Left)
+            .draw(display)
+            .map_err(|e| M5GoError::screen("Unable to draw the panic", e))?;
+        position.y += 22;
+    }
+
+    Ok(())
+}
+
+/// The newest lines that fit in the storage, the oldest one may be cut
+fn log_bytes(tail: &VecDeque<String>) -> Vec<u8> {
+    let text = tail.iter().cloned().collect::<Vec<_>>().join("\n");
+    let mut start = text.len().saturating_sub(MAX_VALUE_LEN);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text.as_bytes()[start..].to_vec()
+}
+
+fn wrap(text: &str) -> Vec<String> {
+    text.lines()
+        .flat_map(|line| {
+            let characters = line.chars().collect::<Vec<_>>();
+            characters
+                .chunks(COLUMNS)
+                .map(|chunk| chunk.iter().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
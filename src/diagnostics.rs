@@ -0,0 +1,565 @@
+//! Guided self-test of the peripherals, for the incoming inspection of new units.
+//!
+//! [`M5Go::self_test`] goes through the buttons, led bars, screen, speaker and ports one after the
+//! other. What only the operator can see or hear is confirmed with the buttons, which is why they
+//! are tested first. Port C is checked with its TX and RX pins wired together.
+
+use std::{
+    fmt,
+    fmt::Debug,
+    ops::RangeInclusive,
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_graphics::{
+    mono_font::ascii::{FONT_10X20, FONT_6X10},
+    pixelcolor::Rgb565,
+    prelude::{DrawTarget, Point, Primitive, RgbColor, Size},
+    primitives::{PrimitiveStyle, Rectangle},
+    text::Alignment,
+    Drawable,
+};
+use smart_leds::{
+    colors::{BLUE, GREEN, RED},
+    RGB8,
+};
+
+use crate::{
+    hal::{AnalogInput, ButtonInput, I2cBus, Platform, SerialPort, ToneOutput},
+    power::Button,
+    screen::Screen,
+    M5Go, M5GoError,
+};
+
+/// How often the buttons are read while waiting for the operator
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Timeout of the bus operations, in RTOS ticks
+const BUS_TIMEOUT: u32 = 100;
+/// Sent on Port C and expected back
+const LOOPBACK_PATTERN: &[u8] = b"M5Go self-test 0123456789\n";
+/// Height of the prompt strip at the bottom of the screen
+const PROMPT_HEIGHT: u32 = 30;
+
+#[derive(Clone, Debug)]
+pub struct DiagnosticsConfig {
+    /// How long each led shows each color
+    pub led_step: Duration,
+    /// How long each test pattern stays on the screen
+    pub screen_step: Duration,
+    /// First and last frequency of the speaker sweep, in Hz, it goes down when the range is
+    /// reversed
+    pub sweep: RangeInclusive<u32>,
+    pub sweep_duration: Duration,
+    /// How long the operator has to press a button
+    pub button_timeout: Duration,
+    /// Addresses that must answer on Port A, any device passes when empty
+    pub expected_devices: Vec<u8>,
+    /// Raw values accepted on Port B
+    pub adc_range: RangeInclusive<u16>,
+    /// Also log the report, which the default logger prints on the serial console
+    pub serial: bool,
+}
+
+impl DiagnosticsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn led_step(mut self, led_step: Duration) -> Self {
+        self.led_step = led_step;
+        self
+    }
+
+    pub fn screen_step(mut self, screen_step: Duration) -> Self {
+        self.screen_step = screen_step;
+        self
+    }
+
+    pub fn sweep(mut self, sweep: RangeInclusive<u32>, duration: Duration) -> Self {
+        self.sweep = sweep;
+        self.sweep_duration = duration;
+        self
+    }
+
+    pub fn button_timeout(mut self, button_timeout: Duration) -> Self {
+        self.button_timeout = button_timeout;
+        self
+    }
+
+    pub fn expect_device(mut self, address: u8) -> Self {
+        self.expected_devices.push(address);
+        self
+    }
+
+    pub fn adc_range(mut self, adc_range: RangeInclusive<u16>) -> Self {
+        self.adc_range = adc_range;
+        self
+    }
+
+    pub fn serial(mut self, serial: bool) -> Self {
+        self.serial = serial;
+        self
+    }
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            led_step: Duration::from_millis(100),
+            screen_step: Duration::from_secs(2),
+            sweep: 200..=4000,
+            sweep_duration: Duration::from_secs(2),
+            button_timeout: Duration::from_secs(10),
+            expected_devices: Vec::new(),
+            adc_range: 0..=4095,
+            serial: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestStatus {
+    Pass,
+    Fail,
+    /// The peripheral is missing or was not claimed
+    Skip,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    pub detail: String,
+}
+
+impl TestResult {
+    fn new(name: &str, status: TestStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            TestStatus::Pass => "PASS",
+            TestStatus::Fail => "FAIL",
+            TestStatus::Skip => "SKIP",
+        };
+        write!(f, "{} {}", status, self.name)?;
+        if !self.detail.is_empty() {
+            write!(f, ": {}", self.detail)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl SelfTestReport {
+    /// Skipped tests do not fail the unit
+    pub fn passed(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| result.status != TestStatus::Fail)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results
+            .iter()
+            .filter(|result| result.status == TestStatus::Fail)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "{}", result)?;
+        }
+        writeln!(
+            f,
+            "Self-test {}",
+            if self.passed() { "passed" } else { "failed" }
+        )
+    }
+}
+
+impl<P: Platform> M5Go<P>
+where
+    <P::Display as DrawTarget>::Error: Debug,
+{
+    /// Run every test, then show the report on the screen. A test failing does not stop the
+    /// following ones.
+    pub fn self_test(&mut self, config: &DiagnosticsConfig) -> Result<SelfTestReport, M5GoError> {
+        self.required_screen()?.turn_on()?;
+
+        let mut report = SelfTestReport::default();
+        for button in [Button::A, Button::B, Button::C] {
+            let name = format!("button {:?}", button);
+            report.results.push(check(&name, || {
+                self.test_button(button, config.button_timeout)
+            }));
+        }
+        report
+            .results
+            .push(check("leds", || self.test_leds(config)));
+        report
+            .results
+            .push(check("screen", || self.test_screen(config)));
+        report
+            .results
+            .push(check("speaker", || self.test_speaker(config)));
+        report
+            .results
+            .push(check("port A (I2C)", || self.test_port_a(config)));
+        report
+            .results
+            .push(check("port B (ADC)", || self.test_port_b(config)));
+        report
+            .results
+            .push(check("port C (UART)", || self.test_port_c()));
+
+        if config.serial {
+            log::info!("{}", report);
+        }
+        self.show_report(&report)?;
+
+        Ok(report)
+    }
+
+    /// Draw the outcome and the result of every test
+    pub fn show_report(&mut self, report: &SelfTestReport) -> Result<(), M5GoError> {
+        let (title, color) = if report.passed() {
+            ("SELF-TEST PASSED", Rgb565::GREEN)
+        } else {
+            ("SELF-TEST FAILED", Rgb565::RED)
+        };
+
+        let screen = self.required_screen()?;
+        screen.fill_background(Rgb565::BLACK)?;
+        screen.draw_text(
+            title,
+            Point::new(5, 20),
+            Alignment::Left,
+            color,
+            &FONT_10X20,
+        )?;
+
+        let mut position = Point::new(5, 45);
+        for result in &report.results {
+            let color = match result.status {
+                TestStatus::Pass => Rgb565::GREEN,
+                TestStatus::Fail => Rgb565::RED,
+                TestStatus::Skip => Rgb565::YELLOW,
+            };
+            screen.draw_text(
+                &result.to_string(),
+                position,
+                Alignment::Left,
+                color,
+                &FONT_6X10,
+            )?;
+            position.y += 12;
+        }
+
+        Ok(())
+    }
+
+    fn test_button(
+        &mut self,
+        button: Button,
+        timeout: Duration,
+    ) -> Result<(TestStatus, String), M5GoError> {
+        self.required_screen()?.fill_background(Rgb565::BLACK)?;
+        self.prompt(&format!("Press {:?}", button))?;
+
+        Ok(match self.wait_button(&[button], timeout) {
+            Some(_) => (TestStatus::Pass, String::new()),
+            None => (TestStatus::Fail, "not pressed in time".to_string()),
+        })
+    }
+
+    /// Every led goes through red, green and blue, one after the other
+    fn test_leds(&mut self, config: &DiagnosticsConfig) -> Result<(TestStatus, String), M5GoError> {
+        let Some(leds) = &mut self.leds else {
+            return Ok((TestStatus::Skip, "no led bars".to_string()));
+        };
+
+        for index in 0..leds.len() {
+            for color in [RED, GREEN, BLUE] {
+                leds.fill(RGB8::default());
+                leds.set_color_at_index(index, color)?;
+                leds.display()?;
+                thread::sleep(config.led_step);
+            }
+        }
+        leds.off()?;
+
+        self.required_screen()?.fill_background(Rgb565::BLACK)?;
+        self.confirm("Did every led show R, G and B?", config.button_timeout)
+    }
+
+    /// Color bars, then a gradient of each channel
+    fn test_screen(
+        &mut self,
+        config: &DiagnosticsConfig,
+    ) -> Result<(TestStatus, String), M5GoError> {
+        let width = self.required_screen()?.width();
+        let height = self.required_screen()?.height() - PROMPT_HEIGHT;
+
+        let bars = [
+            Rgb565::WHITE,
+            Rgb565::YELLOW,
+            Rgb565::CYAN,
+            Rgb565::GREEN,
+            Rgb565::MAGENTA,
+            Rgb565::RED,
+            Rgb565::BLUE,
+            Rgb565::BLACK,
+        ];
+        let bar_width = width / bars.len() as u32;
+        self.required_screen()?.fill_background(Rgb565::BLACK)?;
+        for (index, color) in bars.into_iter().enumerate() {
+            self.fill(
+                Point::new((index as u32 * bar_width) as i32, 0),
+                Size::new(bar_width, height),
+                color,
+            )?;
+        }
+        thread::sleep(config.screen_step);
+
+        let band = height / 3;
+        for x in 0..width {
+            let red = (x * Rgb565::MAX_R as u32 / (width - 1)) as u8;
+            let green = (x * Rgb565::MAX_G as u32 / (width - 1)) as u8;
+            let blue = (x * Rgb565::MAX_B as u32 / (width - 1)) as u8;
+            for (row, color) in [
+                Rgb565::new(red, 0, 0),
+                Rgb565::new(0, green, 0),
+                Rgb565::new(0, 0, blue),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                self.fill(
+                    Point::new(x as i32, (row as u32 * band) as i32),
+                    Size::new(1, band),
+                    color,
+                )?;
+            }
+        }
+        thread::sleep(config.screen_step);
+
+        self.confirm("Bars and gradients OK?", config.button_timeout)
+    }
+
+    fn test_speaker(
+        &mut self,
+        config: &DiagnosticsConfig,
+    ) -> Result<(TestStatus, String), M5GoError> {
+        const STEPS: u32 = 50;
+
+        if self.speaker.is_none() {
+            return Ok((TestStatus::Skip, "no speaker".to_string()));
+        }
+
+        self.required_screen()?.fill_background(Rgb565::BLACK)?;
+        self.prompt("Listen to the speaker")?;
+
+        if let Some(speaker) = &mut self.speaker {
+            let (first, last) = (*config.sweep.start() as i64, *config.sweep.end() as i64);
+            for step in 0..=STEPS as i64 {
+                speaker.play((first + (last - first) * step / STEPS as i64) as u32)?;
+                thread::sleep(config.sweep_duration / STEPS);
+            }
+            speaker.stop()?;
+        }
+
+        self.confirm("Did you hear the sweep?", config.button_timeout)
+    }
+
+    /// Probe every address with an empty write
+    fn test_port_a(
+        &mut self,
+        config: &DiagnosticsConfig,
+    ) -> Result<(TestStatus, String), M5GoError> {
+        let Some(port_a) = &mut self.port_a else {
+            return Ok((TestStatus::Skip, "port not claimed".to_string()));
+        };
+
+        let found = (0x08..0x78)
+            .filter(|&address| port_a.write_to(address, &[], BUS_TIMEOUT).is_ok())
+            .collect::<Vec<u8>>();
+        let missing = config
+            .expected_devices
+            .iter()
+            .filter(|address| !found.contains(address))
+            .collect::<Vec<_>>();
+
+        let mut detail = if found.is_empty() {
+            "no device".to_string()
+        } else {
+            format!("found {:02x?}", found)
+        };
+        if missing.is_empty() {
+            Ok((TestStatus::Pass, detail))
+        } else {
+            detail.push_str(&format!(", missing {:02x?}", missing));
+            Ok((TestStatus::Fail, detail))
+        }
+    }
+
+    fn test_port_b(
+        &mut self,
+        config: &DiagnosticsConfig,
+    ) -> Result<(TestStatus, String), M5GoError> {
+        let Some(port_b) = &mut self.port_b else {
+            return Ok((TestStatus::Skip, "port not claimed".to_string()));
+        };
+
+        let value = port_b.read_raw()?;
+        let status = if config.adc_range.contains(&value) {
+            TestStatus::Pass
+        } else {
+            TestStatus::Fail
+        };
+        Ok((status, format!("read {}", value)))
+    }
+
+    /// Needs TX wired to RX
+    fn test_port_c(&mut self) -> Result<(TestStatus, String), M5GoError> {
+        let Some(port_c) = &mut self.port_c else {
+            return Ok((TestStatus::Skip, "port not claimed".to_string()));
+        };
+
+        // Drop whatever was received before
+        let mut buffer = [0; 64];
+        while port_c.read_bytes(&mut buffer, 0)? > 0 {}
+
+        let mut written = 0;
+        while written < LOOPBACK_PATTERN.len() {
+            match port_c.write_bytes(&LOOPBACK_PATTERN[written..])? {
+                0 => return Ok((TestStatus::Fail, "write stalled".to_string())),
+                count => written += count,
+            }
+        }
+
+        let mut received = Vec::new();
+        while received.len() < LOOPBACK_PATTERN.len() {
+            let count = port_c.read_bytes(&mut buffer, BUS_TIMEOUT)?;
+            if count == 0 {
+                break;
+            }
+            received.extend_from_slice(&buffer[..count]);
+        }
+
+        Ok(if received == LOOPBACK_PATTERN {
+            (TestStatus::Pass, String::new())
+        } else {
+            (
+                TestStatus::Fail,
+                format!(
+                    "received {} of {} bytes back",
+                    received.len(),
+                    LOOPBACK_PATTERN.len()
+                ),
+            )
+        })
+    }
+
+    /// A yes or no question answered with A or C
+    fn confirm(
+        &mut self,
+        question: &str,
+        timeout: Duration,
+    ) -> Result<(TestStatus, String), M5GoError> {
+        self.prompt(&format!("{} A: yes C: no", question))?;
+
+        Ok(match self.wait_button(&[Button::A, Button::C], timeout) {
+            Some(Button::A) => (TestStatus::Pass, String::new()),
+            Some(_) => (TestStatus::Fail, "rejected by the operator".to_string()),
+            None => (TestStatus::Fail, "not confirmed in time".to_string()),
+        })
+    }
+
+    /// The operator follows the prompts on the screen, the self-test cannot run without it
+    fn required_screen(&mut self) -> Result<&mut Screen<P::Display>, M5GoError> {
+        self.screen
+            .as_mut()
+            .ok_or_else(|| M5GoError::screen("The self-test needs the screen", "not set up"))
+    }
+
+    /// Show the text in the strip at the bottom of the screen
+    fn prompt(&mut self, text: &str) -> Result<(), M5GoError> {
+        let width = self.required_screen()?.width();
+        let top = (self.required_screen()?.height() - PROMPT_HEIGHT) as i32;
+        self.fill(
+            Point::new(0, top),
+            Size::new(width, PROMPT_HEIGHT),
+            Rgb565::BLACK,
+        )?;
+        self.required_screen()?.draw_text(
+            text,
+            Point::new(width as i32 / 2, top + 18),
+            Alignment::Center,
+            Rgb565::WHITE,
+            &FONT_6X10,
+        )?;
+        Ok(())
+    }
+
+    fn fill(&mut self, top_left: Point, size: Size, color: Rgb565) -> Result<(), M5GoError> {
+        Rectangle::new(top_left, size)
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(&mut *self.required_screen()?.driver())
+            .map_err(|e| M5GoError::screen("Unable to draw the test pattern", e))
+    }
+
+    /// The first of the buttons pressed then released before the timeout
+    fn wait_button(&self, buttons: &[Button], timeout: Duration) -> Option<Button> {
+        // A button still held from the previous step does not count
+        let started = Instant::now();
+        while buttons.iter().any(|&button| self.is_pressed(button)) {
+            if started.elapsed() > timeout {
+                return None;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        while started.elapsed() <= timeout {
+            if let Some(&button) = buttons.iter().find(|&&button| self.is_pressed(button)) {
+                while self.is_pressed(button) && started.elapsed() <= timeout {
+                    thread::sleep(POLL_INTERVAL);
+                }
+                return Some(button);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        None
+    }
+
+    fn is_pressed(&self, button: Button) -> bool {
+        match button {
+            Button::A => self.button_a.is_pressed(),
+            Button::B => self.button_b.is_pressed(),
+            Button::C => self.button_c.is_pressed(),
+        }
+    }
+}
+
+fn check<F>(name: &str, test: F) -> TestResult
+where
+    F: FnOnce() -> Result<(TestStatus, String), M5GoError>,
+{
+    match test() {
+        Ok((status, detail)) => TestResult::new(name, status, detail),
+        Err(e) => TestResult::new(name, TestStatus::Fail, e.to_string()),
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt::{self, Debug};
+
+/// Failures of the board peripherals, each carrying a description of what went wrong
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum M5GoError {
+    Screen(String),
+    Leds(String),
+    Speaker(String),
+    Gpio(String),
+    Ble(String),
+    I2c(String),
+    Uart(String),
+    Adc(String),
+    Netif(String),
+    Timer(String),
+    Mac(String),
+    Storage(String),
+    Watchdog(String),
+}
+
+/// Constructors prefixing the underlying error with some context, also meant for custom
+/// [`Platform`](crate::hal::Platform) implementations
+impl M5GoError {
+    pub fn screen(context: &str, error: impl Debug) -> Self {
+        Self::Screen(format!("{context}: {error:?}"))
+    }
+
+    pub fn leds(context: &str, error: impl Debug) -> Self {
+        Self::Leds(format!("{context}: {error:?}"))
+    }
+
+    pub fn speaker(context: &str, error: impl Debug) -> Self {
+        Self::Speaker(format!("{context}: {error:?}"))
+    }
+
+    pub fn gpio(context: &str, error: impl Debug) -> Self {
+        Self::Gpio(format!("{context}: {error:?}"))
+    }
+
+    pub fn ble(context: &str, error: impl Debug) -> Self {
+        Self::Ble(format!("{context}: {error:?}"))
+    }
+
+    pub fn i2c(context: &str, error: impl Debug) -> Self {
+        Self::I2c(format!("{context}: {error:?}"))
+    }
+
+    pub fn uart(context: &str, error: impl Debug) -> Self {
+        Self::Uart(format!("{context}: {error:?}"))
+    }
+
+    pub fn adc(context: &str, error: impl Debug) -> Self {
+        Self::Adc(format!("{context}: {error:?}"))
+    }
+
+    pub fn netif(context: &str, error: impl Debug) -> Self {
+        Self::Netif(format!("{context}: {error:?}"))
+    }
+
+    pub fn timer(context: &str, error: impl Debug) -> Self {
+        Self::Timer(format!("{context}: {error:?}"))
+    }
+
+    pub fn mac(context: &str, error: impl Debug) -> Self {
+        Self::Mac(format!("{context}: {error:?}"))
+    }
+
+    pub fn storage(context: &str, error: impl Debug) -> Self {
+        Self::Storage(format!("{context}: {error:?}"))
+    }
+
+    pub fn watchdog(context: &str, error: impl Debug) -> Self {
+        Self::Watchdog(format!("{context}: {error:?}"))
+    }
+}
+
+impl fmt::Display for M5GoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            M5GoError::Screen(message) => write!(f, "Screen error: {message}"),
+            M5GoError::Leds(message) => write!(f, "Leds error: {message}"),
+            M5GoError::Speaker(message) => write!(f, "Speaker error: {message}"),
+            M5GoError::Gpio(message) => write!(f, "GPIO error: {message}"),
+            M5GoError::Ble(message) => write!(f, "BLE error: {message}"),
+            M5GoError::I2c(message) => write!(f, "I2C error: {message}"),
+            M5GoError::Uart(message) => write!(f, "UART error: {message}"),
+            M5GoError::Adc(message) => write!(f, "ADC error: {message}"),
+            M5GoError::Netif(message) => write!(f, "Netif error: {message}"),
+            M5GoError::Timer(message) => write!(f, "Timer error: {message}"),
+            M5GoError::Mac(message) => write!(f, "MAC address error: {message}"),
+            M5GoError::Storage(message) => write!(f, "Storage error: {message}"),
+            M5GoError::Watchdog(message) => write!(f, "Watchdog error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for M5GoError {}
@@ -0,0 +1,83 @@
+//! The ESP-IDF backend, wiring the M5Go peripherals to their pins.
+
+use std::marker::PhantomData;
+
+use esp_idf_hal::{
+    gpio::{Gpio25, Gpio27, Gpio32, Gpio33, Gpio37, Gpio38, Gpio39, Input, PinDriver},
+    i2c::I2cDriver,
+    ledc::{CHANNEL0, TIMER0},
+    prelude::Peripherals,
+    uart::UartDriver,
+};
+use ws2812_esp32_rmt_driver::Ws2812Esp32RmtDriver;
+
+use crate::{
+    ble::{Ble, BleConfig},
+    hal::Platform,
+    io::IOPort,
+    power::EspPower,
+    scheduler::EspScheduler,
+    screen::{EspDisplay, Screen, ScreenDriver},
+    settings::EspStorage,
+    speaker::Speaker,
+    M5Go, M5GoBuilder, M5GoError,
+};
+
+pub type ButtonType<'a, T> = PinDriver<'a, T, Input>;
+
+pub type ButtonAType<'a> = ButtonType<'a, Gpio39>;
+pub type ButtonBType<'a> = ButtonType<'a, Gpio38>;
+pub type ButtonCType<'a> = ButtonType<'a, Gpio37>;
+
+pub type M5GoDisplay<'a> = EspDisplay<'a, Gpio27, Gpio33, Gpio32>;
+
+pub type M5GoScreen<'a> = Screen<M5GoDisplay<'a>>;
+
+pub type M5GoSpeaker = Speaker<Gpio25, CHANNEL0, TIMER0>;
+
+pub type M5GoScreenDriver<'a> = ScreenDriver<'a, Gpio27, Gpio33>;
+
+/// The peripherals of an M5Go driven by ESP-IDF
+pub struct EspPlatform<'a>(PhantomData<&'a ()>);
+
+impl<'a> Platform for EspPlatform<'a> {
+    type Power = EspPower;
+    type Scheduler = EspScheduler;
+    type Storage = EspStorage;
+    type Display = M5GoDisplay<'a>;
+    type LedStrip = Ws2812Esp32RmtDriver;
+    type Tone = M5GoSpeaker;
+    type ButtonA = ButtonAType<'a>;
+    type ButtonB = ButtonBType<'a>;
+    type ButtonC = ButtonCType<'a>;
+    type PortA = I2cDriver<'a>;
+    type PortB = IOPort<'a>;
+    type PortC = UartDriver<'a>;
+}
+
+impl<'a> M5Go<EspPlatform<'a>> {
+    /// Set up every peripheral with the default settings, see [`M5GoBuilder`] to customize them
+    pub fn new(peripherals: Peripherals) -> Result<Self, M5GoError> {
+        M5GoBuilder::new(peripherals)
+            .build()
+            .map(|(m5, _unclaimed)| m5)
+    }
+
+    /// Start BLE, its writes, connections and disconnections are dispatched by [`M5Go::run`].
+    /// Unless configured otherwise, the device is advertised as `M5Go-` followed by its
+    /// [`device_id`](M5Go::device_id).
+    pub fn setup_ble(&mut self, mut config: BleConfig) -> Result<(), M5GoError> {
+        if config.name.is_none() {
+            config = config.name(format!("M5Go-{}", self.device_id()));
+        }
+        if let Some(watchdog) = &self.watchdog {
+            config = config.watchdog(watchdog.clone());
+        }
+        let ble = Ble::new(
+            config.events(self.events.sender()),
+            self.storage.partition(),
+        )?;
+        self.ble = Some(ble);
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+//! A single event loop for the whole device.
+//!
+//! Background tasks, such as the BLE callbacks, post their events to the [`EventQueue`] of the
+//! [`M5Go`]. [`M5Go::run`] blocks on that queue between two polls of the buttons, Port B and
+//! Port C, so nothing busy-waits.
+
+use std::{
+    fmt::Debug,
+    ops::ControlFlow,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
+
+use embedded_graphics::prelude::DrawTarget;
+
+use crate::{
+    buttons::ButtonEvent,
+    hal::{AnalogInput, Platform, SerialPort},
+    navigation::InputAction,
+    power::Button,
+    M5Go, M5GoError,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    ButtonPressed(Button),
+    ButtonReleased(Button),
+    /// See [`ButtonEvent`] for the timing of the following button events
+    ButtonClick(Button),
+    ButtonDoubleClick(Button),
+    ButtonLongPress(Button, Duration),
+    ButtonRepeat(Button),
+    /// The name of a chord registered with [`Buttons::add_chord`](crate::buttons::Buttons::add_chord)
+    ButtonChord(&'static str),
+    /// A navigation action, see [`M5Go::navigation`]
+    Input(InputAction),
+    /// The effect of the [`Animation`](crate::animation::Animation) with this name is over
+    AnimationFinished(&'static str),
+    /// Bytes written by a client to the BLE characteristic
+    BleWrite(Vec<u8>),
+    BleConnected,
+    BleDisconnected,
+    /// A BLE client read the whole crash report, see [`M5Go::clear_crash_report`]
+    CrashReportRead,
+    /// A line received on Port C, without its line ending. A line longer than
+    /// [`EventLoopConfig::max_line_len`] comes in several pieces.
+    UartLine(String),
+    /// The Port B ADC value went over (`rising`) or under the threshold, out of the hysteresis
+    /// band
+    AdcThreshold {
+        value: u16,
+        rising: bool,
+    },
+    Tick,
+}
+
+/// Where events from other tasks are posted
+pub struct EventQueue {
+    sender: Sender<Event>,
+    receiver: Receiver<Event>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        Self { sender, receiver }
+    }
+
+    /// A handle to post events from another task
+    pub fn sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+}
+
+impl From<ButtonEvent> for Event {
+    fn from(event: ButtonEvent) -> Self {
+        match event {
+            ButtonEvent::Pressed(button) => Event::ButtonPressed(button),
+            ButtonEvent::Released(button) => Event::ButtonReleased(button),
+            ButtonEvent::Click(button) => Event::ButtonClick(button),
+            ButtonEvent::DoubleClick(button) => Event::ButtonDoubleClick(button),
+            ButtonEvent::LongPress(button, duration) => Event::ButtonLongPress(button, duration),
+            ButtonEvent::Repeat(button) => Event::ButtonRepeat(button),
+            ButtonEvent::Chord(name) => Event::ButtonChord(name),
+        }
+    }
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EventLoopConfig {
+    /// How often the buttons, Port B and Port C are polled
+    pub poll_interval: Duration,
+    /// Period of [`Event::Tick`], no tick when `None`
+    pub tick: Option<Duration>,
+    /// Raw Port B value for [`Event::AdcThreshold`], Port B is not read when `None`
+    pub adc_threshold: Option<u16>,
+    /// The value rises once over `adc_threshold + adc_hysteresis` and falls once under
+    /// `adc_threshold - adc_hysteresis`, so that the noise around the threshold is ignored
+    pub adc_hysteresis: u16,
+    /// Longest Port C line kept waiting for its line ending, it is sent as it is past that
+    pub max_line_len: usize,
+    /// Longest a handler may take once the watchdog is started, see
+    /// [`M5Go::start_watchdog`]
+    pub watchdog_timeout: Duration,
+}
+
+impl EventLoopConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn tick(mut self, tick: Duration) -> Self {
+        self.tick = Some(tick);
+        self
+    }
+
+    pub fn adc_threshold(mut self, threshold: u16) -> Self {
+        self.adc_threshold = Some(threshold);
+        self
+    }
+
+    pub fn adc_hysteresis(mut self, hysteresis: u16) -> Self {
+        self.adc_hysteresis = hysteresis;
+        self
+    }
+
+    pub fn max_line_len(mut self, max_line_len: usize) -> Self {
+        self.max_line_len = max_line_len;
+        self
+    }
+
+    pub fn watchdog_timeout(mut self, timeout: Duration) -> Self {
+        self.watchdog_timeout = timeout;
+        self
+    }
+}
+
+impl Default for EventLoopConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(20),
+            tick: None,
+            adc_threshold: None,
+            adc_hysteresis: 16,
+            max_line_len: 256,
+            watchdog_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What the loop remembers between two polls
+#[derive(Default)]
+struct PollState {
+    above_threshold: Option<bool>,
+    line: Vec<u8>,
+    next_tick: Option<Instant>,
+    port_b_failing: bool,
+    port_c_failing: bool,
+}
+
+impl<P: Platform> M5Go<P>
+where
+    <P::Display as DrawTarget>::Error: Debug,
+{
+    /// Dispatch every event of the device to `handler` until it breaks
+    ///
+    /// ```ignore
+    /// m5.run(&EventLoopConfig::new(), |m5, event| {
+    ///     match event {
+    ///         Event::ButtonPressed(Button::C) => return ControlFlow::Break(()),
+    ///         event => println!("{:?}", event),
+    ///     }
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// ```
+    pub fn run<F>(&mut self, config: &EventLoopConfig, mut handler: F) -> Result<(), M5GoError>
+    where
+        F: FnMut(&mut Self, Event) -> ControlFlow<()>,
+    {
+        let mut state = PollState {
+            next_tick: config.tick.map(|tick| Instant::now() + tick),
+            ..Default::default()
+        };
+
+        let watchdog = self
+            .watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.register("event loop", config.watchdog_timeout));
+
+        let mut next_poll = Instant::now();
+
+        loop {
+            if let Some(watchdog) = &watchdog {
+                watchdog.feed();
+            }
+
+            let timeout = next_poll.saturating_duration_since(Instant::now());
+            let mut events = match self.events.receiver.recv_timeout(timeout) {
+                Ok(event) => vec![event],
+                // The queue holds a sender, it cannot be disconnected
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => Vec::new(),
+            };
+
+            // Poll on schedule even when other tasks keep posting events
+            if Instant::now() >= next_poll {
+                events.extend(self.poll(config, &mut state));
+                next_poll = Instant::now() + config.poll_interval;
+            }
+
+            for event in events {
+                if handler(self, event).is_break() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// The events since the last poll. A port failing to read, an unplugged unit for example, is
+    /// skipped until it answers again.
+    fn poll(&mut self, config: &EventLoopConfig, state: &mut PollState) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        // Buttons, debounced, then the navigation actions they and the extra inputs make
+        let button_events = self.poll_buttons();
+        let actions = self.navigation.actions(&button_events);
+        events.extend(button_events.into_iter().map(Event::from));
+        events.extend(actions.into_iter().map(Event::Input));
+
+        // Port C
+        if let Some(port_c) = &mut self.port_c {
+            let mut buffer = [0; 64];
+            loop {
+                let read = port_c.read_bytes(&mut buffer, 0);
+                let Some(count) = checked(read, &mut state.port_c_failing, "Port C") else {
+                    break;
+                };
+                state.line.extend_from_slice(&buffer[..count]);
+                take_lines(&mut state.line, config.max_line_len, &mut events);
+                if count < buffer.len() {
+                    break;
+                }
+            }
+        }
+
+        // Port B
+        let port_b = match (config.adc_threshold, &mut self.port_b) {
+            (Some(threshold), Some(port_b)) => {
+                checked(port_b.read_raw(), &mut state.port_b_failing, "Port B")
+                    .map(|value| (threshold, value))
+            }
+            _ => None,
+        };
+        if let Some((threshold, value)) = port_b {
+            let above = match state.above_threshold {
+                None => value > threshold,
+                Some(true) => value >= threshold.saturating_sub(config.adc_hysteresis),
+                Some(false) => value > threshold.saturating_add(config.adc_hysteresis),
+            };
+            if state.above_threshold == Some(!above) {
+                events.push(Event::AdcThreshold {
+                    value,
+                    rising: above,
+                });
+            }
+            state.above_threshold = Some(above);
+        }
+
+        // Tick
+        if let (Some(tick), Some(next_tick)) = (config.tick, state.next_tick) {
+            let now = Instant::now();
+            if now >= next_tick {
+                events.push(Event::Tick);
+                // Skip the ticks missed by a late poll, keeping their pace
+                let behind = now.duration_since(next_tick).as_nanos() % tick.as_nanos().max(1);
+                state.next_tick = Some(now + tick - Duration::from_nanos(behind as u64));
+            }
+        }
+
+        events
+    }
+}
+
+/// The value read, `None` when it failed, with a warning for the first failure in a row
+fn checked<T>(read: Result<T, M5GoError>, failing: &mut bool, source: &str) -> Option<T> {
+    match read {
+        Ok(value) => {
+            *failing = false;
+            Some(value)
+        }
+        Err(e) => {
+            if !*failing {
+                log::warn!("Unable to read {}: {:?}", source, e);
+            }
+            *failing = true;
+            None
+        }
+    }
+}
+
+/// Send the complete lines of `pending`, and what is past `max_line_len` of an unfinished one
+fn take_lines(pending: &mut Vec<u8>, max_line_len: usize, events: &mut Vec<Event>) {
+    let max_line_len = max_line_len.max(1);
+    loop {
+        let line = match pending.iter().position(|byte| *byte == b'\n') {
+            Some(end) if end <= max_line_len => pending.drain(..=end).collect::<Vec<u8>>(),
+            _ if pending.len() >= max_line_len => {
+                pending.drain(..max_line_len).collect::<Vec<u8>>()
+            }
+            _ => return,
+        };
+        let line = String::from_utf8_lossy(&line);
+        events.push(Event::UartLine(
+            line.trim_end_matches(['\r', '\n']).to_string(),
+        ));
+    }
+}
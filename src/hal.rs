@@ -0,0 +1,439 @@
+//! Traits implemented by every peripheral backend.
+//!
+//! [`M5Go`](crate::M5Go) only relies on these, so application logic can be tested with fakes or
+//! reused on other boards by providing another [`Platform`].
+
+use std::time::Duration;
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::{DrawTarget, OriginDimensions},
+};
+use embedded_hal::i2c::Operation;
+use smart_leds::RGB8;
+
+use crate::{
+    power::{ResetReason, WakeCause, WakeSources},
+    M5GoError,
+};
+
+/// A color display with a backlight
+pub trait Display: DrawTarget<Color = Rgb565> + OriginDimensions {
+    fn set_backlight(&mut self, on: bool) -> Result<(), M5GoError>;
+
+    fn is_backlight_on(&self) -> bool;
+}
+
+/// A strip of addressable RGB leds
+pub trait LedStrip {
+    /// Send the colors to the strip, the first one being the closest to the data pin
+    fn write_pixels(&mut self, pixels: &[RGB8]) -> Result<(), M5GoError>;
+}
+
+/// Something that can play a square wave tone
+pub trait ToneOutput {
+    fn play(&mut self, freq: u32) -> Result<(), M5GoError>;
+
+    fn stop(&mut self) -> Result<(), M5GoError>;
+
+    /// The frequency being played, if any
+    fn playing(&self) -> Option<u32>;
+}
+
+/// A push button
+pub trait ButtonInput {
+    fn is_pressed(&self) -> bool;
+}
+
+/// A push button reporting its edges from an interrupt handler
+pub trait ButtonInterrupt: ButtonInput {
+    /// Call `callback` with whether the button is pressed on every edge, replacing the previous
+    /// callback
+    ///
+    /// # Safety
+    ///
+    /// `callback` runs in an interrupt handler, it must not block, allocate nor log.
+    unsafe fn subscribe_edges<F>(&mut self, callback: F) -> Result<(), M5GoError>
+    where
+        F: FnMut(bool) + Send + 'static;
+
+    fn unsubscribe_edges(&mut self) -> Result<(), M5GoError>;
+}
+
+/// An analog input, read as a raw ADC value
+pub trait AnalogInput {
+    fn read_raw(&mut self) -> Result<u16, M5GoError>;
+}
+
+/// A pin that can be driven and read back, like the GPIO of Port B
+pub trait DigitalIo {
+    fn set_level(&mut self, high: bool) -> Result<(), M5GoError>;
+
+    fn is_high(&self) -> bool;
+}
+
+/// An I2C master, timeouts are in RTOS ticks
+pub trait I2cBus {
+    fn write_to(&mut self, address: u8, bytes: &[u8], timeout: u32) -> Result<(), M5GoError>;
+
+    fn read_from(&mut self, address: u8, buffer: &mut [u8], timeout: u32) -> Result<(), M5GoError>;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+        timeout: u32,
+    ) -> Result<(), M5GoError>;
+
+    /// Run the operations as the embedded-hal transaction: a start, a repeated start where the
+    /// direction changes and a single stop at the end
+    ///
+    /// By default the adjacent operations of a direction are merged, and what is left is sent
+    /// with the methods above. That leaves out a write after a read, which is an error.
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        let first_read = operations
+            .iter()
+            .position(|operation| matches!(operation, Operation::Read(_)))
+            .unwrap_or(operations.len());
+        let (writes, reads) = operations.split_at_mut(first_read);
+
+        let mut bytes = Vec::new();
+        for operation in writes.iter() {
+            if let Operation::Write(part) = operation {
+                bytes.extend_from_slice(part);
+            }
+        }
+        let mut buffer = Vec::new();
+        for operation in reads.iter() {
+            match operation {
+                Operation::Read(part) => buffer.resize(buffer.len() + part.len(), 0),
+                Operation::Write(_) => {
+                    return Err(M5GoError::I2c(
+                        "Unable to write after a read without a stop between them".to_string(),
+                    ))
+                }
+            }
+        }
+
+        match (writes.is_empty(), reads.is_empty()) {
+            (true, true) => return Ok(()),
+            (false, true) => return self.write_to(address, &bytes, timeout),
+            (true, false) => self.read_from(address, &mut buffer, timeout)?,
+            (false, false) => self.write_read(address, &bytes, &mut buffer, timeout)?,
+        }
+
+        let mut received = buffer.as_slice();
+        for operation in reads {
+            if let Operation::Read(part) = operation {
+                let (head, tail) = received.split_at(part.len());
+                part.copy_from_slice(head);
+                received = tail;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: I2cBus + ?Sized> I2cBus for &mut T {
+    fn write_to(&mut self, address: u8, bytes: &[u8], timeout: u32) -> Result<(), M5GoError> {
+        (**self).write_to(address, bytes, timeout)
+    }
+
+    fn read_from(&mut self, address: u8, buffer: &mut [u8], timeout: u32) -> Result<(), M5GoError> {
+        (**self).read_from(address, buffer, timeout)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        (**self).write_read(address, bytes, buffer, timeout)
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        (**self).transaction(address, operations, timeout)
+    }
+}
+
+/// A serial port, timeouts are in RTOS ticks
+pub trait SerialPort {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, M5GoError>;
+
+    fn read_bytes(&mut self, buffer: &mut [u8], timeout: u32) -> Result<usize, M5GoError>;
+}
+
+/// Longest value a [`Storage`] keeps, longer writes fail
+pub const MAX_VALUE_LEN: usize = 1024;
+
+/// Persistent key-value storage, keys are at most 15 characters long
+pub trait Storage {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, M5GoError>;
+
+    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), M5GoError>;
+
+    /// Returns whether the key existed
+    fn remove(&mut self, key: &str) -> Result<bool, M5GoError>;
+}
+
+impl<T: Storage + ?Sized> Storage for &mut T {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, M5GoError> {
+        (**self).read(key)
+    }
+
+    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), M5GoError> {
+        (**self).write(key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, M5GoError> {
+        (**self).remove(key)
+    }
+}
+
+/// Sleep modes of the chip
+pub trait PowerManagement {
+    /// Sleep until one of the sources wakes the chip up, then resume where it stopped
+    fn light_sleep(&mut self, wakeup: &WakeSources) -> Result<WakeCause, M5GoError>;
+
+    /// Sleep until one of the sources wakes the chip up, which then reboots
+    fn deep_sleep(&mut self, wakeup: &WakeSources) -> !;
+
+    /// What woke the chip up from its last sleep
+    fn wake_cause(&self) -> WakeCause;
+
+    /// Why the chip started
+    fn reset_reason(&self) -> ResetReason;
+}
+
+/// Runs callbacks once after a delay or periodically, outside of the calling task
+pub trait Scheduler {
+    /// Handle of a scheduled callback, dropping it cancels the callback
+    type Timer: TimerHandle;
+
+    fn once<F>(&self, delay: Duration, callback: F) -> Result<Self::Timer, M5GoError>
+    where
+        F: FnMut() + Send + 'static;
+
+    fn every<F>(&self, period: Duration, callback: F) -> Result<Self::Timer, M5GoError>
+    where
+        F: FnMut() + Send + 'static;
+
+    /// Time elapsed since boot
+    fn now(&self) -> Duration;
+}
+
+pub trait TimerHandle {
+    /// Cancel the callback, returns whether it was still scheduled
+    fn cancel(&self) -> Result<bool, M5GoError>;
+
+    fn is_scheduled(&self) -> Result<bool, M5GoError>;
+}
+
+/// The set of peripheral types a board is made of
+pub trait Platform {
+    type Power: PowerManagement;
+    type Scheduler: Scheduler;
+    type Storage: Storage;
+    type Display: Display;
+    type LedStrip: LedStrip;
+    type Tone: ToneOutput;
+    type ButtonA: ButtonInput;
+    type ButtonB: ButtonInput;
+    type ButtonC: ButtonInput;
+    type PortA: I2cBus;
+    type PortB: AnalogInput + DigitalIo;
+    type PortC: SerialPort;
+}
+
+#[cfg(feature = "esp")]
+mod esp {
+    use embedded_hal::i2c::Operation;
+    use esp_idf_hal::{
+        gpio::{Input, InputPin, InterruptType, Pin, PinDriver},
+        i2c::I2cDriver,
+        uart::UartDriver,
+    };
+    use esp_idf_sys::*;
+
+    use super::{ButtonInput, ButtonInterrupt, I2cBus, SerialPort};
+    use crate::M5GoError;
+
+    impl<'a, T: Pin> ButtonInput for PinDriver<'a, T, Input> {
+        fn is_pressed(&self) -> bool {
+            // The front buttons are pulled up, pressing one pulls it low
+            self.is_low()
+        }
+    }
+
+    impl<'a, T: InputPin> ButtonInterrupt for PinDriver<'a, T, Input> {
+        unsafe fn subscribe_edges<F>(&mut self, mut callback: F) -> Result<(), M5GoError>
+        where
+            F: FnMut(bool) + Send + 'static,
+        {
+            self.set_interrupt_type(InterruptType::AnyEdge)
+                .map_err(|e| M5GoError::gpio("Unable to set the interrupt type", e))?;
+            let pin = self.pin();
+            self.subscribe(move || callback(esp_idf_sys::gpio_get_level(pin) == 0))
+                .map_err(|e| M5GoError::gpio("Unable to subscribe to the button", e))
+        }
+
+        fn unsubscribe_edges(&mut self) -> Result<(), M5GoError> {
+            self.unsubscribe()
+                .map_err(|e| M5GoError::gpio("Unable to unsubscribe from the button", e))
+        }
+    }
+
+    impl<'a> I2cBus for I2cDriver<'a> {
+        fn write_to(&mut self, address: u8, bytes: &[u8], timeout: u32) -> Result<(), M5GoError> {
+            self.write(address, bytes, timeout)
+                .map_err(|e| M5GoError::i2c("Write failed", e))
+        }
+
+        fn read_from(
+            &mut self,
+            address: u8,
+            buffer: &mut [u8],
+            timeout: u32,
+        ) -> Result<(), M5GoError> {
+            self.read(address, buffer, timeout)
+                .map_err(|e| M5GoError::i2c("Read failed", e))
+        }
+
+        fn write_read(
+            &mut self,
+            address: u8,
+            bytes: &[u8],
+            buffer: &mut [u8],
+            timeout: u32,
+        ) -> Result<(), M5GoError> {
+            I2cDriver::write_read(self, address, bytes, buffer, timeout)
+                .map_err(|e| M5GoError::i2c("Write then read failed", e))
+        }
+
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation],
+            timeout: u32,
+        ) -> Result<(), M5GoError> {
+            if operations.is_empty() {
+                return Ok(());
+            }
+            let link = CommandLink::new()?;
+            link.queue(address, operations)
+                .map_err(|e| M5GoError::i2c("Unable to queue the transaction", e))?;
+            esp!(unsafe { i2c_master_cmd_begin(self.port(), link.0, timeout) })
+                .map_err(|e| M5GoError::i2c("Transaction failed", e))
+        }
+    }
+
+    /// Commands run by the I2C driver in one go, deleted on drop
+    struct CommandLink(i2c_cmd_handle_t);
+
+    impl CommandLink {
+        fn new() -> Result<Self, M5GoError> {
+            let handle = unsafe { i2c_cmd_link_create() };
+            if handle.is_null() {
+                return Err(M5GoError::i2c(
+                    "Unable to create the command link",
+                    ESP_ERR_NO_MEM,
+                ));
+            }
+            Ok(Self(handle))
+        }
+
+        /// The buffers are read and written when the link runs, they must outlive it
+        fn queue(&self, address: u8, operations: &mut [Operation]) -> Result<(), EspError> {
+            let reads = operations
+                .iter()
+                .map(|operation| match operation {
+                    Operation::Read(buffer) => Some(buffer.len()),
+                    Operation::Write(_) => None,
+                })
+                .collect::<Vec<_>>();
+
+            let mut reading = None;
+            for (i, operation) in operations.iter_mut().enumerate() {
+                let read = matches!(operation, Operation::Read(_));
+                if reading != Some(read) {
+                    // A start, then a repeated start each time the direction changes
+                    let direction = if read {
+                        i2c_rw_t_I2C_MASTER_READ
+                    } else {
+                        i2c_rw_t_I2C_MASTER_WRITE
+                    };
+                    unsafe {
+                        esp!(i2c_master_start(self.0))?;
+                        esp!(i2c_master_write_byte(
+                            self.0,
+                            (address << 1) | direction as u8,
+                            true
+                        ))?;
+                    }
+                    reading = Some(read);
+                }
+
+                match operation {
+                    Operation::Write(bytes) if !bytes.is_empty() => unsafe {
+                        esp!(i2c_master_write(self.0, bytes.as_ptr(), bytes.len(), true))?;
+                    },
+                    Operation::Read(buffer) if !buffer.is_empty() => {
+                        // The last byte read before a repeated start or the stop is not
+                        // acknowledged, so that the device releases SDA
+                        let last_read = reads[i + 1..]
+                            .iter()
+                            .take_while(|next| next.is_some())
+                            .all(|next| *next == Some(0));
+                        let ack = if last_read {
+                            i2c_ack_type_t_I2C_MASTER_LAST_NACK
+                        } else {
+                            i2c_ack_type_t_I2C_MASTER_ACK
+                        };
+                        unsafe {
+                            esp!(i2c_master_read(
+                                self.0,
+                                buffer.as_mut_ptr(),
+                                buffer.len(),
+                                ack
+                            ))?;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            esp!(unsafe { i2c_master_stop(self.0) })
+        }
+    }
+
+    impl Drop for CommandLink {
+        fn drop(&mut self) {
+            unsafe { i2c_cmd_link_delete(self.0) };
+        }
+    }
+
+    impl<'a> SerialPort for UartDriver<'a> {
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, M5GoError> {
+            self.write(bytes)
+                .map_err(|e| M5GoError::uart("Write failed", e))
+        }
+
+        fn read_bytes(&mut self, buffer: &mut [u8], timeout: u32) -> Result<usize, M5GoError> {
+            self.read(buffer, timeout)
+                .map_err(|e| M5GoError::uart("Read failed", e))
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Shared access to the internal I2C bus, which carries Port A, the IP5306 and the IMU.
+//!
+//! [`SharedI2c`] owns the bus behind a mutex and hands out [`I2cDevice`]s, each with its own
+//! timeout. A device implements [`I2cBus`] for the drivers of this crate and the embedded-hal
+//! [`I2c`] trait for the others, so several drivers can use the bus from different tasks.
+//!
+//! ```ignore
+//! let bus = m5.share_port_a().unwrap().with_recovery(EspBusRecovery::port_a());
+//! let mut battery = Ip5306::new(bus.device());
+//! let env = Sht3x::new(bus.device().timeout(10));
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation, SevenBitAddress};
+use log::warn;
+
+use crate::{
+    hal::{I2cBus, Platform},
+    M5Go, M5GoError,
+};
+
+/// Timeout of a new device, in RTOS ticks
+const DEFAULT_TIMEOUT: u32 = 100;
+
+/// Frees a bus whose SDA line is held low by a device, after a reset in the middle of a read
+/// for example
+pub trait BusRecovery: Send {
+    /// Clock SCL until SDA is released, returns whether the bus was stuck
+    fn recover(&mut self) -> Result<bool, M5GoError>;
+}
+
+struct BusState<B> {
+    bus: B,
+    recovery: Option<Box<dyn BusRecovery>>,
+    /// How many times the bus had to be freed
+    recoveries: u32,
+}
+
+/// Cloning the bus gives another handle on the same driver
+pub struct SharedI2c<B: I2cBus> {
+    state: Arc<Mutex<BusState<B>>>,
+}
+
+impl<B: I2cBus> Clone for SharedI2c<B> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<B: I2cBus + Send> SharedI2c<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BusState {
+                bus,
+                recovery: None,
+                recoveries: 0,
+            })),
+        }
+    }
+
+    /// Try to free the bus when an operation fails, the operation is retried once if it was stuck
+    pub fn with_recovery<R: BusRecovery + 'static>(self, recovery: R) -> Self {
+        self.lock().recovery = Some(Box::new(recovery));
+        self
+    }
+
+    /// A new handle on the bus, with the default timeout
+    pub fn device(&self) -> I2cDevice<B> {
+        I2cDevice {
+            bus: self.clone(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Free the bus right away, returns whether it was stuck
+    pub fn recover(&self) -> Result<bool, M5GoError> {
+        let mut state = self.lock();
+        let recovered = match &mut state.recovery {
+            Some(recovery) => recovery.recover()?,
+            None => false,
+        };
+        if recovered {
+            state.recoveries += 1;
+        }
+        Ok(recovered)
+    }
+
+    pub fn recoveries(&self) -> u32 {
+        self.lock().recoveries
+    }
+
+    /// Give the bus back, fails while other handles or devices are alive
+    pub fn release(self) -> Result<B, Self> {
+        Arc::try_unwrap(self.state)
+            .map(|state| {
+                state
+                    .into_inner()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .bus
+            })
+            .map_err(|state| Self { state })
+    }
+
+    /// Run `f` with the bus locked, so that other devices cannot interleave their operations
+    fn with<T, F>(&self, mut f: F) -> Result<T, M5GoError>
+    where
+        F: FnMut(&mut B) -> Result<T, M5GoError>,
+    {
+        let mut state = self.lock();
+        let error = match f(&mut state.bus) {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+
+        let Some(recovery) = &mut state.recovery else {
+            return Err(error);
+        };
+        match recovery.recover() {
+            Ok(true) => {
+                warn!("I2C bus was stuck, retrying after {}", error);
+                state.recoveries += 1;
+                f(&mut state.bus)
+            }
+            Ok(false) => Err(error),
+            Err(e) => {
+                warn!("Unable to recover the I2C bus: {}", e);
+                Err(error)
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BusState<B>> {
+        // The bus holds no invariant a panicking task could break
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A handle on the shared bus with its own timeout
+pub struct I2cDevice<B: I2cBus> {
+    bus: SharedI2c<B>,
+    timeout: u32,
+}
+
+impl<B: I2cBus> Clone for I2cDevice<B> {
+    fn clone(&self) -> Self {
+        Self {
+            bus: self.bus.clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<B: I2cBus + Send> I2cDevice<B> {
+    /// Timeout of the embedded-hal operations, in RTOS ticks. The [`I2cBus`] operations take
+    /// theirs as a parameter.
+    pub fn timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn bus(&self) -> &SharedI2c<B> {
+        &self.bus
+    }
+}
+
+impl<B: I2cBus + Send> I2cBus for I2cDevice<B> {
+    fn write_to(&mut self, address: u8, bytes: &[u8], timeout: u32) -> Result<(), M5GoError> {
+        self.bus.with(|bus| bus.write_to(address, bytes, timeout))
+    }
+
+    fn read_from(&mut self, address: u8, buffer: &mut [u8], timeout: u32) -> Result<(), M5GoError> {
+        self.bus.with(|bus| bus.read_from(address, buffer, timeout))
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        self.bus
+            .with(|bus| bus.write_read(address, bytes, buffer, timeout))
+    }
+
+    /// The bus stays locked for the whole transaction
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        self.bus
+            .with(|bus| bus.transaction(address, operations, timeout))
+    }
+}
+
+impl embedded_hal::i2c::Error for M5GoError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<B: I2cBus> ErrorType for I2cDevice<B> {
+    type Error = M5GoError;
+}
+
+impl<B: I2cBus + Send> I2c<SevenBitAddress> for I2cDevice<B> {
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let timeout = self.timeout;
+        self.read_from(address, buffer, timeout)
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        let timeout = self.timeout;
+        self.write_to(address, bytes, timeout)
+    }
+
+    fn write_iter<I>(&mut self, address: u8, bytes: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        self.write(address, &bytes.into_iter().collect::<Vec<_>>())
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let timeout = self.timeout;
+        I2cBus::write_read(self, address, bytes, buffer, timeout)
+    }
+
+    fn write_iter_read<I>(
+        &mut self,
+        address: u8,
+        bytes: I,
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        I2c::write_read(
+            self,
+            address,
+            &bytes.into_iter().collect::<Vec<_>>(),
+            buffer,
+        )
+    }
+
+    fn transaction<'a>(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'a>],
+    ) -> Result<(), Self::Error> {
+        let timeout = self.timeout;
+        I2cBus::transaction(self, address, operations, timeout)
+    }
+
+    fn transaction_iter<'a, O>(&mut self, address: u8, operations: O) -> Result<(), Self::Error>
+    where
+        O: IntoIterator<Item = Operation<'a>>,
+    {
+        I2c::transaction(
+            self,
+            address,
+            &mut operations.into_iter().collect::<Vec<_>>(),
+        )
+    }
+}
+
+impl<P: Platform> M5Go<P>
+where
+    P::PortA: Send,
+{
+    /// Move Port A behind a shared handle, `port_a` is `None` afterwards. `None` when the port
+    /// was left unclaimed or already shared.
+    pub fn share_port_a(&mut self) -> Option<SharedI2c<P::PortA>> {
+        self.port_a
+            .take()
+            .map(|port| SharedI2c::new(port.release()))
+    }
+}
+
+#[cfg(feature = "esp")]
+pub use esp::EspBusRecovery;
+
+#[cfg(feature = "esp")]
+mod esp {
+    use esp_idf_sys::*;
+
+    use super::BusRecovery;
+    use crate::M5GoError;
+
+    /// Clock pulses sent at most to make the device release SDA, a byte and its ACK
+    const CLOCK_PULSES: u32 = 9;
+    /// Half of a 100 kHz clock period
+    const HALF_PERIOD_US: u32 = 5;
+
+    /// Bit-bangs the bus pins, then gives them back to the I2C controller with its FIFOs reset
+    pub struct EspBusRecovery {
+        port: i2c_port_t,
+        sda: i32,
+        scl: i32,
+    }
+
+    impl EspBusRecovery {
+        pub fn new(port: i2c_port_t, sda: i32, scl: i32) -> Self {
+            Self { port, sda, scl }
+        }
+
+        /// The internal bus, on I2C0 with SDA on GPIO21 and SCL on GPIO22
+        pub fn port_a() -> Self {
+            Self::new(0, 21, 22)
+        }
+    }
+
+    impl BusRecovery for EspBusRecovery {
+        fn recover(&mut self) -> Result<bool, M5GoError> {
+            if unsafe { gpio_get_level(self.sda) } != 0 {
+                return Ok(false);
+            }
+
+            unsafe {
+                esp!(gpio_set_direction(
+                    self.scl,
+                    gpio_mode_t_GPIO_MODE_INPUT_OUTPUT_OD
+                ))
+                .map_err(|e| M5GoError::i2c("Unable to take SCL", e))?;
+                esp!(gpio_set_direction(
+                    self.sda,
+                    gpio_mode_t_GPIO_MODE_INPUT_OUTPUT_OD
+                ))
+                .map_err(|e| M5GoError::i2c("Unable to take SDA", e))?;
+
+                // The device only releases SDA on a 1 bit or on the ACK
+                gpio_set_level(self.scl, 0);
+                gpio_set_level(self.sda, 1);
+                esp_rom_delay_us(HALF_PERIOD_US);
+                let mut pulses = 0;
+                while gpio_get_level(self.sda) == 0 && pulses < CLOCK_PULSES {
+                    gpio_set_level(self.scl, 1);
+                    esp_rom_delay_us(HALF_PERIOD_US);
+                    gpio_set_level(self.scl, 0);
+                    esp_rom_delay_us(HALF_PERIOD_US);
+                    pulses += 1;
+                }
+
+                // STOP: SDA rises while SCL is high
+                gpio_set_level(self.sda, 0);
+                gpio_set_level(self.scl, 1);
+                esp_rom_delay_us(HALF_PERIOD_US);
+                gpio_set_level(self.sda, 1);
+                esp_rom_delay_us(HALF_PERIOD_US);
+
+                esp!(i2c_set_pin(
+                    self.port,
+                    self.sda,
+                    self.scl,
+                    true,
+                    true,
+                    i2c_mode_t_I2C_MODE_MASTER
+                ))
+                .map_err(|e| M5GoError::i2c("Unable to give the pins back", e))?;
+
+                // Drop what the failed command left in the controller
+                esp!(i2c_reset_tx_fifo(self.port))
+                    .map_err(|e| M5GoError::i2c("Unable to reset the TX FIFO", e))?;
+                esp!(i2c_reset_rx_fifo(self.port))
+                    .map_err(|e| M5GoError::i2c("Unable to reset the RX FIFO", e))?;
+            }
+
+            Ok(true)
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use esp_idf_hal::{
     adc::{AdcChannelDriver, AdcConfig, AdcDriver, Atten11dB, ADC1},
-    gpio::{Gpio26, Gpio36, InputOutput, PinDriver},
+    gpio::{Gpio26, Gpio36, InputOutput, Level, PinDriver},
+};
+
+use crate::{
+    hal::{AnalogInput, DigitalIo},
+    M5GoError,
 };
 
 pub struct IOPort<'a> {
@@ -10,10 +15,13 @@ pub struct IOPort<'a> {
 }
 
 impl<'a> IOPort<'a> {
-    pub fn new(in_out: Gpio26, input: Gpio36, adc1: ADC1) -> anyhow::Result<Self> {
-        let in_out = PinDriver::input_output(in_out)?;
-        let driver = AdcDriver::new(adc1, &AdcConfig::new())?;
-        let channel_driver = AdcChannelDriver::new(input)?;
+    pub fn new(in_out: Gpio26, input: Gpio36, adc1: ADC1) -> Result<Self, M5GoError> {
+        let in_out = PinDriver::input_output(in_out)
+            .map_err(|e| M5GoError::gpio("Unable to set up GPIO26", e))?;
+        let driver = AdcDriver::new(adc1, &AdcConfig::new())
+            .map_err(|e| M5GoError::adc("Unable to set up ADC1", e))?;
+        let channel_driver = AdcChannelDriver::new(input)
+            .map_err(|e| M5GoError::adc("Unable to set up GPIO36 as ADC channel", e))?;
         Ok(Self {
             in_out,
             driver,
@@ -21,7 +29,27 @@ impl<'a> IOPort<'a> {
         })
     }
 
-    pub fn read(&mut self) -> anyhow::Result<u16> {
-        Ok(self.driver.read(&mut self.channel_driver)?)
+    pub fn read(&mut self) -> Result<u16, M5GoError> {
+        self.driver
+            .read(&mut self.channel_driver)
+            .map_err(|e| M5GoError::adc("Read failed", e))
+    }
+}
+
+impl<'a> AnalogInput for IOPort<'a> {
+    fn read_raw(&mut self) -> Result<u16, M5GoError> {
+        self.read()
+    }
+}
+
+impl<'a> DigitalIo for IOPort<'a> {
+    fn set_level(&mut self, high: bool) -> Result<(), M5GoError> {
+        self.in_out
+            .set_level(Level::from(high))
+            .map_err(|e| M5GoError::gpio("Unable to drive GPIO26", e))
+    }
+
+    fn is_high(&self) -> bool {
+        self.in_out.is_high()
     }
 }
@@ -1,41 +1,175 @@
-use esp_idf_sys::gpio_num_t;
+use std::sync::{Arc, Mutex, MutexGuard};
+
 use smart_leds::RGB8;
+#[cfg(feature = "esp")]
 use ws2812_esp32_rmt_driver::Ws2812Esp32RmtDriver;
 
-/// A driver for the side led bars
-pub struct Leds {
-    driver: Ws2812Esp32RmtDriver,
+use crate::{hal::LedStrip, M5GoError};
+
+/// Where a strip of WS2812 leds is plugged
+///
+/// The default is the side bars of the M5Go. An external strip needs an RMT channel of its own,
+/// on Port B (GPIO26) or on a pin of Port C (GPIO16 or GPIO17) once the port is skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedConfig {
+    pub pin: i32,
+    /// RMT channel, 0 to 7
+    pub channel: u8,
+    pub count: usize,
+}
+
+impl LedConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pin(mut self, pin: i32) -> Self {
+        self.pin = pin;
+        self
+    }
+
+    pub fn channel(mut self, channel: u8) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+}
+
+impl Default for LedConfig {
+    fn default() -> Self {
+        Self {
+            pin: 15,
+            channel: 0,
+            count: 10,
+        }
+    }
+}
+
+/// A driver for a strip of leds, the side bars or an external one
+///
+/// The strip is shared with the panic hook, which only lights it when nobody else is.
+pub struct Leds<S: LedStrip> {
+    driver: Arc<Mutex<S>>,
     lights: Vec<RGB8>,
+    /// What the strip shows, `None` until first written
+    shown: Option<Vec<RGB8>>,
+}
+
+#[cfg(feature = "esp")]
+impl LedStrip for Ws2812Esp32RmtDriver {
+    /// The frame goes out in a single RMT transmission, a gap between two leds longer than the
+    /// reset time would end the frame there
+    fn write_pixels(&mut self, pixels: &[RGB8]) -> Result<(), M5GoError> {
+        let frame = pixels
+            .iter()
+            .flat_map(|color| color.as_ref().iter().copied())
+            .collect::<Vec<u8>>();
+        self.write(&frame)
+            .map_err(|e| M5GoError::leds("Write failed", e))
+    }
+}
+
+#[cfg(feature = "esp")]
+impl Leds<Ws2812Esp32RmtDriver> {
+    pub fn new(config: &LedConfig) -> Result<Self, M5GoError> {
+        let driver = Ws2812Esp32RmtDriver::new(config.channel, config.pin as u32).map_err(|e| {
+            M5GoError::leds(
+                &format!(
+                    "Error creating leds driver from pin {} on RMT channel {}",
+                    config.pin, config.channel
+                ),
+                e,
+            )
+        })?;
+
+        Ok(Self::from_strip(driver, config.count))
+    }
 }
 
-impl Leds {
-    pub fn new(gpio_num: gpio_num_t) -> Self {
-        let driver = Ws2812Esp32RmtDriver::new(0, 15)
-            .expect(format!("Error creating leds driver from pin {gpio_num}").as_str());
-        let lights = vec![RGB8::default(); 10];
+impl<S: LedStrip> Leds<S> {
+    pub fn from_strip(driver: S, count: usize) -> Self {
+        let lights = vec![RGB8::default(); count];
+
+        Self {
+            driver: Arc::new(Mutex::new(driver)),
+            lights,
+            shown: None,
+        }
+    }
+
+    /// The number of leds on the strip
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn strip(&self) -> MutexGuard<'_, S> {
+        self.driver.lock().unwrap_or_else(|p| p.into_inner())
+    }
 
-        Self { driver, lights }
+    pub(crate) fn shared_strip(&self) -> Arc<Mutex<S>> {
+        self.driver.clone()
     }
 
-    /// Light the lights up
-    pub fn display(&mut self) {
-        for color in &self.lights {
-            self.driver.write(color.as_ref()).unwrap();
+    /// The colors shown by the next `display`
+    pub fn pixels(&self) -> &[RGB8] {
+        &self.lights
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [RGB8] {
+        &mut self.lights
+    }
+
+    /// Light the lights up, nothing is sent when the strip already shows these colors
+    pub fn display(&mut self) -> Result<(), M5GoError> {
+        if self.shown.as_ref() == Some(&self.lights) {
+            return Ok(());
         }
+        self.refresh()
+    }
+
+    /// Send the colors even if the strip should already show them, after it lost power for
+    /// example
+    pub fn refresh(&mut self) -> Result<(), M5GoError> {
+        self.shown = None;
+        self.strip().write_pixels(&self.lights)?;
+        self.shown = Some(self.lights.clone());
+        Ok(())
+    }
+
+    /// Fails when `index` is past the end of the strip
+    pub fn set_color_at_index(&mut self, index: usize, color: RGB8) -> Result<(), M5GoError> {
+        let light = self
+            .lights
+            .get_mut(index)
+            .ok_or_else(|| M5GoError::leds("Index out of range", index))?;
+        *light = color;
+        Ok(())
     }
 
-    pub fn set_color_at_index(&mut self, index: usize, color: RGB8) {
-        self.lights.remove(index);
-        self.lights.insert(index, color);
+    /// Turn the leds off without forgetting their colors, `display` lights them up again
+    pub fn blank(&mut self) -> Result<(), M5GoError> {
+        let black = vec![RGB8::default(); self.lights.len()];
+        self.shown = None;
+        self.strip().write_pixels(&black)?;
+        self.shown = Some(black);
+        Ok(())
     }
 
-    pub fn off(&mut self) {
-        self.lights = std::iter::repeat(RGB8::default()).take(10).collect();
-        self.display();
+    pub fn off(&mut self) -> Result<(), M5GoError> {
+        self.lights.fill(RGB8::default());
+        self.display()
     }
 
     pub fn fill(&mut self, color: RGB8) {
-        self.lights = vec![color; 10]
+        self.lights.fill(color);
     }
 }
 
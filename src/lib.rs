@@ -1,138 +1,98 @@
+#[cfg(all(feature = "esp", feature = "sim"))]
+compile_error!("Features `esp` and `sim` are mutually exclusive, build the simulator with `--no-default-features --features sim`");
+
+pub mod animation;
+pub mod battery;
+#[cfg(feature = "esp")]
 pub mod ble;
+pub mod board;
+#[cfg(feature = "esp")]
+mod builder;
+pub mod button_queue;
+pub mod buttons;
+pub mod console;
+pub mod crash;
+pub mod diagnostics;
+mod error;
+#[cfg(feature = "esp")]
+mod esp;
+pub mod event;
+pub mod hal;
+pub mod i2c;
+#[cfg(feature = "esp")]
 pub mod io;
 pub mod leds;
+pub mod mac;
+pub mod navigation;
+pub mod ports;
+pub mod power;
+pub mod scheduler;
 pub mod screen;
+pub mod settings;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "esp")]
 pub mod speaker;
-
-use std::sync::Arc;
-
-use ble::{Ble, BleConfig};
-use esp_idf_svc::netif::{EspNetif, NetifStack};
-
-use esp_idf_hal::{
-    gpio::{Gpio25, Gpio27, Gpio32, Gpio33, Gpio37, Gpio38, Gpio39, Gpio8, Input, PinDriver},
-    i2c::{I2cConfig, I2cDriver},
-    ledc::{CHANNEL0, TIMER0},
-    prelude::Peripherals,
-    uart::{UartConfig, UartDriver},
-    units::Hertz,
-};
-use io::IOPort;
-
+pub mod watchdog;
+
+#[cfg(feature = "esp")]
+pub use builder::*;
+pub use error::M5GoError;
+#[cfg(feature = "esp")]
+pub use esp::*;
+
+#[cfg(feature = "esp")]
+use ble::Ble;
+use board::Imu;
+use buttons::Buttons;
+use event::EventQueue;
+use hal::Platform;
 use leds::Leds;
-use screen::{Screen, ScreenDriver};
-use speaker::Speaker;
-
-pub type ButtonType<'a, T> = PinDriver<'a, T, Input>;
-
-pub type ButtonAType<'a> = ButtonType<'a, Gpio39>;
-pub type ButtonBType<'a> = ButtonType<'a, Gpio38>;
-pub type ButtonCType<'a> = ButtonType<'a, Gpio37>;
-
-pub type M5GoScreen<'a> = Screen<'a, Gpio27, Gpio33, Gpio32>;
-
-pub type M5GoSpeaker = Speaker<Gpio25, CHANNEL0, TIMER0>;
-
-pub type M5GoScreenDriver<'a> = ScreenDriver<'a, Gpio27, Gpio33>;
-
-pub struct M5Go<'a> {
-    pub button_a: ButtonAType<'a>,
-    pub button_b: ButtonBType<'a>,
-    pub button_c: ButtonCType<'a>,
-    pub leds: Leds,
-    pub screen: M5GoScreen<'a>,
-    pub port_a: I2cDriver<'a>,
-    pub port_b: IOPort<'a>,
-    pub port_c: UartDriver<'a>,
-    pub speaker: M5GoSpeaker,
+use mac::MacAddress;
+use navigation::Navigation;
+use ports::{PortA, PortB, PortC};
+use screen::Screen;
+use watchdog::Watchdog;
+
+pub struct M5Go<P: Platform> {
+    pub button_a: P::ButtonA,
+    pub button_b: P::ButtonB,
+    pub button_c: P::ButtonC,
+    /// Debounced state and timing of the buttons, updated by [`M5Go::run`]
+    pub buttons: Buttons,
+    /// Maps the buttons and the extra inputs to [`navigation::InputAction`]s
+    pub navigation: Navigation,
+    /// `None` on boards without led bars, or when left unclaimed by the `M5GoBuilder`
+    pub leds: Option<Leds<P::LedStrip>>,
+    /// `None` when left unclaimed by the `M5GoBuilder`
+    pub screen: Option<Screen<P::Display>>,
+    /// `None` when the port was left unclaimed by the `M5GoBuilder`
+    pub port_a: Option<PortA<P::PortA>>,
+    pub port_b: Option<PortB<P::PortB>>,
+    pub port_c: Option<PortC<P::PortC>>,
+    /// `None` when left unclaimed by the `M5GoBuilder`
+    pub speaker: Option<P::Tone>,
+    pub power: P::Power,
+    pub scheduler: P::Scheduler,
+    /// Raw persistent storage, see [`M5Go::settings`] for typed access
+    pub storage: P::Storage,
+    #[cfg(feature = "esp")]
     pub ble: Option<Ble>,
-    pub mac: String,
-}
-
-fn get_mac(mac: [u8; 6]) -> String {
-    let mut mac_str = String::new();
-    for (i, byte) in mac.iter().enumerate() {
-        if i > 0 {
-            mac_str.push(':');
-        }
-        mac_str.push_str(&format!("{:02X}", byte));
-    }
-    mac_str
+    /// MAC of the Wi-Fi station interface
+    pub mac: MacAddress,
+    pub bt_mac: MacAddress,
+    /// The IMU on the internal I2C bus, if the board has one
+    pub imu: Option<Imu>,
+    /// Events posted from other tasks, dispatched by [`M5Go::run`]
+    pub events: EventQueue,
+    /// Set by [`M5Go::start_watchdog`]
+    pub watchdog: Option<Watchdog>,
 }
 
-impl<'a> M5Go<'a> {
-    pub fn new(peripherals: Peripherals) -> anyhow::Result<Self> {
-        let netif_stack =
-            Arc::new(EspNetif::new(NetifStack::Sta).expect("Unable to init Netif Stack"));
-
-        let mac = get_mac(netif_stack.get_mac().expect("Unable to get MAC address"));
-
-        let i2c = peripherals.i2c0;
-        let sda = peripherals.pins.gpio21;
-        let scl = peripherals.pins.gpio22;
-
-        let config = I2cConfig::new();
-        let port_a = I2cDriver::new(i2c, sda, scl, &config)?;
-
-        // Port C
-        let port_c_config = UartConfig::new().baudrate(Hertz(9600));
-        let port_c = UartDriver::new(
-            peripherals.uart2,
-            peripherals.pins.gpio17,
-            peripherals.pins.gpio16,
-            None as Option<Gpio8>,
-            None as Option<Gpio8>,
-            &port_c_config,
-        )?;
-
-        // Port B
-        let io_b = peripherals.pins.gpio26;
-        let input_b = peripherals.pins.gpio36;
-        let adc1 = peripherals.adc1;
-        let port_b = IOPort::new(io_b, input_b, adc1)?;
-
-        // Buttons
-        let button_a = PinDriver::input(peripherals.pins.gpio39)?;
-        let button_b = PinDriver::input(peripherals.pins.gpio38)?;
-        let button_c = PinDriver::input(peripherals.pins.gpio37)?;
-
-        // Screen
-        let blk = peripherals.pins.gpio32;
-        let sclk = peripherals.pins.gpio18;
-        let sdo = peripherals.pins.gpio23;
-        let cs = peripherals.pins.gpio14;
-        let dc = peripherals.pins.gpio27;
-        let reset = peripherals.pins.gpio33;
-
-        let screen = Screen::new(cs, sdo, sclk, dc, reset, blk, peripherals.spi2);
-
-        // Leds
-        let leds = Leds::new(15);
-
-        // Speaker
-        let speaker_pin = peripherals.pins.gpio25;
-        let channel0 = peripherals.ledc.channel0;
-        let timer0 = peripherals.ledc.timer0;
-        let speaker = Speaker::new(speaker_pin, channel0, timer0);
-
-        Ok(Self {
-            button_a,
-            button_b,
-            button_c,
-            leds,
-            screen,
-            port_a,
-            port_b,
-            port_c,
-            speaker,
-            ble: None,
-            mac,
-        })
-    }
-
-    pub fn setup_ble(&mut self, config: BleConfig) {
-        let ble = Ble::new(config);
-        self.ble = Some(ble);
+impl<P: Platform> M5Go<P> {
+    /// A short identifier, stable across reboots, to tell devices of a fleet apart
+    pub fn device_id(&self) -> String {
+        self.mac.short_id()
     }
 }
 
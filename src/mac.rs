@@ -0,0 +1,197 @@
+//! MAC addresses and the device identifier derived from them.
+
+use std::{fmt, str::FromStr};
+
+use crate::M5GoError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MacAddress([u8; 6]);
+
+impl MacAddress {
+    pub const fn new(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn bytes(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// `AA:BB:CC:DD:EE:FF`, also what `Display` prints
+    pub fn colon(&self) -> String {
+        self.join(":")
+    }
+
+    /// `AA-BB-CC-DD-EE-FF`
+    pub fn dash(&self) -> String {
+        self.join("-")
+    }
+
+    /// `AABBCCDDEEFF`
+    pub fn compact(&self) -> String {
+        self.join("")
+    }
+
+    /// The last three bytes, which differ between devices of a same vendor, e.g. `DDEEFF`
+    pub fn short_id(&self) -> String {
+        self.0[3..]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect()
+    }
+
+    fn join(&self, separator: &str) -> String {
+        self.0
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.colon())
+    }
+}
+
+impl From<[u8; 6]> for MacAddress {
+    fn from(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+}
+
+/// Parses the colon, dash and compact formats, in either case
+impl FromStr for MacAddress {
+    type Err = M5GoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = match s.len() {
+            12 => s.to_string(),
+            17 => {
+                let separator = s.as_bytes()[2];
+                if separator != b':' && separator != b'-' {
+                    return Err(M5GoError::mac("Unknown separator", s));
+                }
+                let mut digits = String::new();
+                for (i, c) in s.chars().enumerate() {
+                    match i % 3 {
+                        2 if c as u32 == separator as u32 => (),
+                        2 => return Err(M5GoError::mac("Mixed separators", s)),
+                        _ => digits.push(c),
+                    }
+                }
+                digits
+            }
+            _ => return Err(M5GoError::mac("Invalid length", s)),
+        };
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(M5GoError::mac("Invalid hexadecimal digits", s));
+        }
+
+        let mut bytes = [0; 6];
+        for (byte, i) in bytes.iter_mut().zip((0..12).step_by(2)) {
+            *byte = digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| M5GoError::mac("Invalid length", s))?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+#[cfg(feature = "esp")]
+mod esp {
+    use esp_idf_sys::*;
+
+    use super::MacAddress;
+    use crate::M5GoError;
+
+    impl MacAddress {
+        /// MAC of the Wi-Fi station interface, read from the eFuses
+        pub fn wifi_sta() -> Result<Self, M5GoError> {
+            Self::read(esp_mac_type_t_ESP_MAC_WIFI_STA)
+        }
+
+        /// MAC used for Bluetooth, read from the eFuses
+        pub fn bluetooth() -> Result<Self, M5GoError> {
+            Self::read(esp_mac_type_t_ESP_MAC_BT)
+        }
+
+        fn read(kind: esp_mac_type_t) -> Result<Self, M5GoError> {
+            let mut bytes = [0; 6];
+            esp!(unsafe { esp_read_mac(bytes.as_mut_ptr(), kind) })
+                .map_err(|e| M5GoError::mac("Unable to read MAC address", e))?;
+            Ok(Self(bytes))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: MacAddress = MacAddress::new([0xaa, 0xbb, 0xcc, 0x0d, 0xee, 0x0f]);
+
+    #[test]
+    fn formats() {
+        assert_eq!(MAC.colon(), "AA:BB:CC:0D:EE:0F");
+        assert_eq!(MAC.dash(), "AA-BB-CC-0D-EE-0F");
+        assert_eq!(MAC.compact(), "AABBCC0DEE0F");
+        assert_eq!(MAC.to_string(), MAC.colon());
+    }
+
+    #[test]
+    fn short_id() {
+        assert_eq!(MAC.short_id(), "0DEE0F");
+    }
+
+    #[test]
+    fn parse() {
+        for s in [
+            "AA:BB:CC:0D:EE:0F",
+            "aa-bb-cc-0d-ee-0f",
+            "AABBCC0DEE0F",
+            "aabbcc0dee0f",
+        ] {
+            assert_eq!(s.parse::<MacAddress>().unwrap(), MAC, "{}", s);
+        }
+        assert_eq!(MAC.dash().parse::<MacAddress>().unwrap(), MAC);
+    }
+
+    #[test]
+    fn parse_rejects_a_bad_length() {
+        for s in [
+            "",
+            "AA:BB:CC:0D:EE",
+            "AABBCC0DEE0",
+            "AABBCC0DEE0F0",
+            "AA:BB:CC:0D:EE:0F:",
+        ] {
+            assert!(s.parse::<MacAddress>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_bad_separators() {
+        for s in [
+            "AA.BB.CC.0D.EE.0F",
+            "AA:BB-CC:0D:EE:0F",
+            "AAB:BCC:0DE:E0F:00",
+        ] {
+            assert!(s.parse::<MacAddress>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_bad_hex() {
+        for s in [
+            "AA:BB:CC:0D:EE:0G",
+            "GABBCC0DEE0F",
+            "+ABBCC0DEE0F",
+            "AA:BB:CC:0D:EE:é",
+        ] {
+            assert!(s.parse::<MacAddress>().is_err(), "{}", s);
+        }
+    }
+}
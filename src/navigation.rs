@@ -0,0 +1,315 @@
+//! Navigation actions for the UI, mapped from the buttons and from optional input units.
+//!
+//! Menus consume [`InputAction`]s rather than button events, so they work the same with the
+//! three front buttons alone or with an encoder or a potentiometer on the Grove ports:
+//!
+//! ```ignore
+//! let bus = m5.share_port_a().unwrap();
+//! m5.navigation.add_source(Encoder::new(bus.device()));
+//! m5.run(&EventLoopConfig::new(), |m5, event| {
+//!     if let Event::Input(action) = event {
+//!         menu.apply(action);
+//!     }
+//!     ControlFlow::Continue(())
+//! })?;
+//! ```
+
+use crate::{
+    buttons::ButtonEvent,
+    hal::{AnalogInput, I2cBus, Platform},
+    power::Button,
+    M5Go, M5GoError,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputAction {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+/// A button event an action can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Pressed(Button),
+    Click(Button),
+    DoubleClick(Button),
+    LongPress(Button),
+    Repeat(Button),
+    /// A chord registered with [`Buttons::add_chord`](crate::buttons::Buttons::add_chord)
+    Chord(&'static str),
+}
+
+impl Trigger {
+    fn matches(&self, event: &ButtonEvent) -> bool {
+        match (*self, *event) {
+            (Trigger::Pressed(button), ButtonEvent::Pressed(pressed)) => button == pressed,
+            (Trigger::Click(button), ButtonEvent::Click(clicked)) => button == clicked,
+            (Trigger::DoubleClick(button), ButtonEvent::DoubleClick(clicked)) => button == clicked,
+            (Trigger::LongPress(button), ButtonEvent::LongPress(held, _)) => button == held,
+            (Trigger::Repeat(button), ButtonEvent::Repeat(held)) => button == held,
+            (Trigger::Chord(name), ButtonEvent::Chord(chord)) => name == chord,
+            _ => false,
+        }
+    }
+}
+
+/// Which button events make which actions
+///
+/// The default map follows the M5Stack menus: A goes up, C goes down, both repeating while held,
+/// B selects and a long press on B goes back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputMap {
+    bindings: Vec<(Trigger, InputAction)>,
+}
+
+impl InputMap {
+    /// A map without bindings
+    pub fn new() -> Self {
+        Self {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Several triggers can make the same action, and a trigger several actions
+    pub fn bind(mut self, trigger: Trigger, action: InputAction) -> Self {
+        self.bindings.push((trigger, action));
+        self
+    }
+
+    /// Remove the bindings of a trigger, returns whether it had any
+    pub fn unbind(&mut self, trigger: Trigger) -> bool {
+        let count = self.bindings.len();
+        self.bindings.retain(|(bound, _)| *bound != trigger);
+        self.bindings.len() != count
+    }
+
+    pub fn map(&self, event: &ButtonEvent) -> impl Iterator<Item = InputAction> + '_ {
+        let event = *event;
+        self.bindings
+            .iter()
+            .filter(move |(trigger, _)| trigger.matches(&event))
+            .map(|(_, action)| *action)
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self::new()
+            .bind(Trigger::Click(Button::A), InputAction::Up)
+            .bind(Trigger::Repeat(Button::A), InputAction::Up)
+            .bind(Trigger::Click(Button::C), InputAction::Down)
+            .bind(Trigger::Repeat(Button::C), InputAction::Down)
+            .bind(Trigger::Click(Button::B), InputAction::Select)
+            .bind(Trigger::LongPress(Button::B), InputAction::Back)
+    }
+}
+
+/// An input unit producing actions by itself, polled with the buttons
+pub trait NavigationInput {
+    fn poll(&mut self) -> Result<Vec<InputAction>, M5GoError>;
+}
+
+/// The buttons map and the extra inputs
+pub struct Navigation {
+    map: InputMap,
+    /// With whether the last poll failed, so that an unplugged unit is only reported once
+    sources: Vec<(Box<dyn NavigationInput + Send>, bool)>,
+}
+
+impl Navigation {
+    pub fn new(map: InputMap) -> Self {
+        Self {
+            map,
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn map(&self) -> &InputMap {
+        &self.map
+    }
+
+    pub fn set_map(&mut self, map: InputMap) {
+        self.map = map;
+    }
+
+    pub fn add_source<S: NavigationInput + Send + 'static>(&mut self, source: S) {
+        self.sources.push((Box::new(source), false));
+    }
+
+    /// The actions of the button events, followed by those of the extra inputs
+    ///
+    /// An input failing, an unplugged unit for example, is skipped until it answers again.
+    pub fn actions(&mut self, events: &[ButtonEvent]) -> Vec<InputAction> {
+        let mut actions = events
+            .iter()
+            .flat_map(|event| self.map.map(event))
+            .collect::<Vec<_>>();
+        for (source, failing) in &mut self.sources {
+            match source.poll() {
+                Ok(polled) => {
+                    *failing = false;
+                    actions.extend(polled);
+                }
+                Err(e) => {
+                    if !*failing {
+                        log::warn!("Unable to poll a navigation input: {:?}", e);
+                    }
+                    *failing = true;
+                }
+            }
+        }
+        actions
+    }
+}
+
+impl Default for Navigation {
+    fn default() -> Self {
+        Self::new(InputMap::default())
+    }
+}
+
+/// A potentiometer, like the Angle unit on Port B, turned through detents
+///
+/// Turning it by one detent is an [`InputAction::Down`] clockwise and an [`InputAction::Up`]
+/// the other way.
+pub struct Potentiometer<A: AnalogInput> {
+    input: A,
+    detents: u16,
+    /// Raw values per detent
+    step: u16,
+    /// Detent the knob rests in, `None` until first read
+    detent: Option<i32>,
+}
+
+impl<A: AnalogInput> Potentiometer<A> {
+    /// `detents` over the full 12 bit range
+    pub fn new(input: A, detents: u16) -> Self {
+        let detents = detents.max(1);
+        Self {
+            input,
+            detents,
+            step: 4096 / detents,
+            detent: None,
+        }
+    }
+
+    pub fn release(self) -> A {
+        self.input
+    }
+
+    fn detent_of(&self, value: i32) -> i32 {
+        (value / self.step as i32).min(self.detents as i32 - 1)
+    }
+}
+
+impl<A: AnalogInput> NavigationInput for Potentiometer<A> {
+    fn poll(&mut self) -> Result<Vec<InputAction>, M5GoError> {
+        let value = self.input.read_raw()? as i32;
+        let new = self.detent_of(value);
+        let Some(detent) = self.detent else {
+            self.detent = Some(new);
+            return Ok(Vec::new());
+        };
+
+        // A quarter of a detent of hysteresis, so that a noisy reading on a boundary is ignored
+        let step = self.step as i32;
+        let center = detent * step + step / 2;
+        if new == detent || (value - center).abs() < step * 3 / 4 {
+            return Ok(Vec::new());
+        }
+        self.detent = Some(new);
+        let moved = new - detent;
+        Ok(if moved > 0 {
+            vec![InputAction::Down; moved as usize]
+        } else {
+            vec![InputAction::Up; moved.unsigned_abs() as usize]
+        })
+    }
+}
+
+/// Default address of the Encoder unit
+pub const ENCODER_ADDRESS: u8 = 0x40;
+
+/// The M5Stack Encoder unit, on the I2C bus of Port A
+///
+/// Each step clockwise is an [`InputAction::Down`], each step the other way an
+/// [`InputAction::Up`], and pressing the knob an [`InputAction::Select`].
+pub struct Encoder<B: I2cBus> {
+    bus: B,
+    address: u8,
+    timeout: u32,
+    /// Counter and button read last, `None` until first read
+    last: Option<(i16, bool)>,
+}
+
+impl<B: I2cBus> Encoder<B> {
+    const COUNTER: u8 = 0x10;
+    const BUTTON: u8 = 0x20;
+
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            address: ENCODER_ADDRESS,
+            timeout: 10,
+            last: None,
+        }
+    }
+
+    /// When the unit was given another address
+    pub fn address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Timeout of the reads, in RTOS ticks
+    pub fn timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    fn read(&mut self) -> Result<(i16, bool), M5GoError> {
+        let mut counter = [0; 2];
+        self.bus
+            .write_read(self.address, &[Self::COUNTER], &mut counter, self.timeout)?;
+        let mut button = [0; 1];
+        self.bus
+            .write_read(self.address, &[Self::BUTTON], &mut button, self.timeout)?;
+        // The button reads 0 while pressed
+        Ok((i16::from_le_bytes(counter), button[0] == 0))
+    }
+}
+
+impl<B: I2cBus> NavigationInput for Encoder<B> {
+    fn poll(&mut self) -> Result<Vec<InputAction>, M5GoError> {
+        let (counter, pressed) = self.read()?;
+        let Some((last_counter, was_pressed)) = self.last.replace((counter, pressed)) else {
+            return Ok(Vec::new());
+        };
+
+        // The counter wraps around
+        let steps = counter.wrapping_sub(last_counter);
+        let mut actions = match steps.signum() {
+            1 => vec![InputAction::Down; steps as usize],
+            -1 => vec![InputAction::Up; steps.unsigned_abs() as usize],
+            _ => Vec::new(),
+        };
+        if pressed && !was_pressed {
+            actions.push(InputAction::Select);
+        }
+        Ok(actions)
+    }
+}
+
+impl<P: Platform> M5Go<P> {
+    /// Read the buttons and the extra inputs, when not using [`M5Go::run`]
+    pub fn poll_input(&mut self) -> Vec<InputAction> {
+        let events = self.poll_buttons();
+        self.navigation.actions(&events)
+    }
+}
@@ -0,0 +1,340 @@
+//! The Grove ports, wrapped to implement the embedded-hal and embedded-io traits.
+//!
+//! Drivers from the ecosystem can then be given a port directly, taken out of the
+//! [`M5Go`](crate::M5Go). The embedded-hal version is the one esp-idf-hal implements. Each wrapper
+//! also derefs to the driver it wraps, for its own methods.
+//!
+//! ```ignore
+//! let port_a = m5.port_a.take().unwrap();
+//! let mut sensor = Sht3x::new(port_a, Address::Low);
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use embedded_hal::{
+    digital::{self, InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin},
+    i2c::{self, I2c, Operation, SevenBitAddress},
+};
+use embedded_io::{
+    blocking::{Read, Write},
+    Io,
+};
+
+use crate::{
+    hal::{AnalogInput, DigitalIo, I2cBus, SerialPort},
+    M5GoError,
+};
+
+/// Timeout of the operations of a new port, in RTOS ticks
+const DEFAULT_TIMEOUT: u32 = 100;
+
+impl embedded_io::Error for M5GoError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+/// Port A, the I2C bus
+pub struct PortA<B: I2cBus> {
+    bus: B,
+    timeout: u32,
+}
+
+impl<B: I2cBus> PortA<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Timeout of the embedded-hal operations, in RTOS ticks
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+impl<B: I2cBus> Deref for PortA<B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        &self.bus
+    }
+}
+
+impl<B: I2cBus> DerefMut for PortA<B> {
+    fn deref_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+}
+
+impl<B: I2cBus> I2cBus for PortA<B> {
+    fn write_to(&mut self, address: u8, bytes: &[u8], timeout: u32) -> Result<(), M5GoError> {
+        self.bus.write_to(address, bytes, timeout)
+    }
+
+    fn read_from(&mut self, address: u8, buffer: &mut [u8], timeout: u32) -> Result<(), M5GoError> {
+        self.bus.read_from(address, buffer, timeout)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        self.bus.write_read(address, bytes, buffer, timeout)
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        self.bus.transaction(address, operations, timeout)
+    }
+}
+
+impl<B: I2cBus> i2c::ErrorType for PortA<B> {
+    type Error = M5GoError;
+}
+
+impl<B: I2cBus> I2c<SevenBitAddress> for PortA<B> {
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus.read_from(address, buffer, self.timeout)
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.bus.write_to(address, bytes, self.timeout)
+    }
+
+    fn write_iter<I>(&mut self, address: u8, bytes: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        I2c::write(self, address, &bytes.into_iter().collect::<Vec<_>>())
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.bus.write_read(address, bytes, buffer, self.timeout)
+    }
+
+    fn write_iter_read<I>(
+        &mut self,
+        address: u8,
+        bytes: I,
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        I2c::write_read(
+            self,
+            address,
+            &bytes.into_iter().collect::<Vec<_>>(),
+            buffer,
+        )
+    }
+
+    fn transaction<'a>(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'a>],
+    ) -> Result<(), Self::Error> {
+        self.bus.transaction(address, operations, self.timeout)
+    }
+
+    fn transaction_iter<'a, O>(&mut self, address: u8, operations: O) -> Result<(), Self::Error>
+    where
+        O: IntoIterator<Item = Operation<'a>>,
+    {
+        I2c::transaction(
+            self,
+            address,
+            &mut operations.into_iter().collect::<Vec<_>>(),
+        )
+    }
+}
+
+/// Port B, a GPIO on GPIO26 and an analog input on GPIO36
+///
+/// embedded-hal has no ADC trait, the analog input is read with [`AnalogInput::read_raw`].
+pub struct PortB<T: AnalogInput + DigitalIo> {
+    io: T,
+    /// Level the GPIO is driven to, it reads back lower when something pulls it down
+    set_high: bool,
+}
+
+impl<T: AnalogInput + DigitalIo> PortB<T> {
+    pub fn new(io: T) -> Self {
+        Self {
+            set_high: io.is_high(),
+            io,
+        }
+    }
+
+    pub fn release(self) -> T {
+        self.io
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> Deref for PortB<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.io
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> DerefMut for PortB<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> AnalogInput for PortB<T> {
+    fn read_raw(&mut self) -> Result<u16, M5GoError> {
+        self.io.read_raw()
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> DigitalIo for PortB<T> {
+    fn set_level(&mut self, high: bool) -> Result<(), M5GoError> {
+        self.io.set_level(high)?;
+        self.set_high = high;
+        Ok(())
+    }
+
+    fn is_high(&self) -> bool {
+        self.io.is_high()
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> digital::ErrorType for PortB<T> {
+    type Error = M5GoError;
+}
+
+impl<T: AnalogInput + DigitalIo> InputPin for PortB<T> {
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.io.is_high())
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.io.is_high())
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> OutputPin for PortB<T> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_level(false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_level(true)
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> StatefulOutputPin for PortB<T> {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.set_high)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.set_high)
+    }
+}
+
+impl<T: AnalogInput + DigitalIo> ToggleableOutputPin for PortB<T> {
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        self.set_level(!self.set_high)
+    }
+}
+
+/// Port C, the UART
+pub struct PortC<S: SerialPort> {
+    serial: S,
+    timeout: u32,
+}
+
+impl<S: SerialPort> PortC<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// How long each embedded-io read waits for data before trying again, in RTOS ticks
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    pub fn release(self) -> S {
+        self.serial
+    }
+}
+
+impl<S: SerialPort> Deref for PortC<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.serial
+    }
+}
+
+impl<S: SerialPort> DerefMut for PortC<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+}
+
+impl<S: SerialPort> SerialPort for PortC<S> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, M5GoError> {
+        self.serial.write_bytes(bytes)
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8], timeout: u32) -> Result<usize, M5GoError> {
+        self.serial.read_bytes(buffer, timeout)
+    }
+}
+
+impl<S: SerialPort> Io for PortC<S> {
+    type Error = M5GoError;
+}
+
+impl<S: SerialPort> Read for PortC<S> {
+    /// Blocks until at least one byte is received
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let count = self.serial.read_bytes(buffer, self.timeout)?;
+            if count > 0 {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+impl<S: SerialPort> Write for PortC<S> {
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.serial.write_bytes(bytes)
+    }
+
+    /// The bytes are in the driver's buffer once written, nothing is held back here
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::{fmt::Debug, time::Duration};
+
+use embedded_graphics::prelude::DrawTarget;
+
+use crate::{
+    hal::{Platform, PowerManagement, ToneOutput},
+    M5Go, M5GoError,
+};
+
+/// The three front buttons
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    A,
+    B,
+    C,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WakeCause {
+    /// Power on or reset, the chip was not sleeping
+    PowerOn,
+    Timer,
+    Button(Button),
+    Other,
+}
+
+/// Why the chip last started
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    PowerOn,
+    /// The reset pin or button
+    External,
+    /// `esp_restart` or equivalent
+    Software,
+    Panic,
+    InterruptWatchdog,
+    TaskWatchdog,
+    /// Any other watchdog
+    Watchdog,
+    DeepSleep,
+    Brownout,
+    Unknown,
+}
+
+impl ResetReason {
+    /// Whether the reset was not asked for
+    pub fn is_crash(&self) -> bool {
+        matches!(
+            self,
+            ResetReason::Panic
+                | ResetReason::InterruptWatchdog
+                | ResetReason::TaskWatchdog
+                | ResetReason::Watchdog
+                | ResetReason::Brownout
+                | ResetReason::Unknown
+        )
+    }
+}
+
+/// What may wake the chip up, the first one to happen wins
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WakeSources {
+    pub timer: Option<Duration>,
+    pub button: Option<Button>,
+}
+
+impl WakeSources {
+    pub fn timer(duration: Duration) -> Self {
+        Self {
+            timer: Some(duration),
+            button: None,
+        }
+    }
+
+    pub fn button(button: Button) -> Self {
+        Self {
+            timer: None,
+            button: Some(button),
+        }
+    }
+}
+
+impl<P: Platform> M5Go<P>
+where
+    <P::Display as DrawTarget>::Error: Debug,
+{
+    /// Sleep for `duration`, turning the screen, leds and speaker off meanwhile
+    pub fn light_sleep_for(&mut self, duration: Duration) -> Result<WakeCause, M5GoError> {
+        self.light_sleep(&WakeSources::timer(duration))
+    }
+
+    /// Sleep until `button` is pressed, turning the screen, leds and speaker off meanwhile
+    pub fn light_sleep_until_button(&mut self, button: Button) -> Result<WakeCause, M5GoError> {
+        self.light_sleep(&WakeSources::button(button))
+    }
+
+    /// Turn the screen, leds and speaker off then sleep, restoring them once woken up
+    pub fn light_sleep(&mut self, wakeup: &WakeSources) -> Result<WakeCause, M5GoError> {
+        let screen_on = matches!(&self.screen, Some(screen) if screen.is_on());
+        let tone = self.speaker.as_ref().and_then(|speaker| speaker.playing());
+
+        self.power_down()?;
+        let cause = self.power.light_sleep(wakeup)?;
+
+        if let Some(screen) = self.screen.as_mut().filter(|_| screen_on) {
+            screen.turn_on()?;
+        }
+        if let Some(leds) = &mut self.leds {
+            leds.display()?;
+        }
+        if let (Some(speaker), Some(freq)) = (&mut self.speaker, tone) {
+            speaker.play(freq)?;
+        }
+
+        Ok(cause)
+    }
+
+    /// Turn the screen, leds and speaker off then sleep for `duration`, the device reboots after
+    pub fn deep_sleep_for(&mut self, duration: Duration) -> ! {
+        self.deep_sleep(&WakeSources::timer(duration))
+    }
+
+    /// Turn the screen, leds and speaker off then sleep until `button` is pressed, the device
+    /// reboots after
+    pub fn deep_sleep_until_button(&mut self, button: Button) -> ! {
+        self.deep_sleep(&WakeSources::button(button))
+    }
+
+    pub fn deep_sleep(&mut self, wakeup: &WakeSources) -> ! {
+        // Nothing can be reported once asleep, go to sleep even if something failed
+        self.power_down().ok();
+        self.power.deep_sleep(wakeup)
+    }
+
+    /// What woke the device up, [`WakeCause::PowerOn`] after a regular boot
+    pub fn wake_cause(&self) -> WakeCause {
+        self.power.wake_cause()
+    }
+
+    pub fn reset_reason(&self) -> ResetReason {
+        self.power.reset_reason()
+    }
+
+    fn power_down(&mut self) -> Result<(), M5GoError> {
+        if let Some(screen) = &mut self.screen {
+            screen.turn_off()?;
+        }
+        if let Some(leds) = &mut self.leds {
+            leds.blank()?;
+        }
+        if let Some(speaker) = &mut self.speaker {
+            speaker.stop()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "esp")]
+pub use esp::EspPower;
+
+#[cfg(feature = "esp")]
+mod esp {
+    use esp_idf_sys::*;
+
+    use super::{Button, ResetReason, WakeCause, WakeSources};
+    use crate::{hal::PowerManagement, M5GoError};
+
+    /// Sleep modes of the ESP32, the buttons being on RTC GPIOs they can wake it from both
+    pub struct EspPower {
+        buttons: [gpio_num_t; 3],
+    }
+
+    impl EspPower {
+        /// Buttons A, B and C GPIOs, as set up by the builder
+        pub fn new(buttons: [gpio_num_t; 3]) -> Self {
+            Self { buttons }
+        }
+
+        fn gpio(&self, button: Button) -> gpio_num_t {
+            match button {
+                Button::A => self.buttons[0],
+                Button::B => self.buttons[1],
+                Button::C => self.buttons[2],
+            }
+        }
+
+        fn pressed_button(&self) -> Option<Button> {
+            [Button::A, Button::B, Button::C]
+                .into_iter()
+                .find(|button| unsafe { gpio_get_level(self.gpio(*button)) } == 0)
+        }
+
+        fn configure(&self, wakeup: &WakeSources, deep: bool) -> Result<(), EspError> {
+            unsafe {
+                esp!(esp_sleep_disable_wakeup_source(
+                    esp_sleep_source_t_ESP_SLEEP_WAKEUP_ALL
+                ))?;
+
+                if let Some(duration) = wakeup.timer {
+                    esp!(esp_sleep_enable_timer_wakeup(duration.as_micros() as u64))?;
+                }
+
+                if let Some(button) = wakeup.button {
+                    let gpio = self.gpio(button);
+                    if deep {
+                        // The buttons are pulled up, pressing one pulls its pin low
+                        esp!(esp_sleep_enable_ext1_wakeup(
+                            1 << gpio,
+                            esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ALL_LOW
+                        ))?;
+                    } else {
+                        esp!(gpio_wakeup_enable(
+                            gpio,
+                            gpio_int_type_t_GPIO_INTR_LOW_LEVEL
+                        ))?;
+                        esp!(esp_sleep_enable_gpio_wakeup())?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl PowerManagement for EspPower {
+        fn light_sleep(&mut self, wakeup: &WakeSources) -> Result<WakeCause, M5GoError> {
+            self.configure(wakeup, false)
+                .map_err(|e| M5GoError::gpio("Unable to configure the wake up sources", e))?;
+
+            let result = esp!(unsafe { esp_light_sleep_start() });
+
+            if let Some(button) = wakeup.button {
+                unsafe { gpio_wakeup_disable(self.gpio(button)) };
+            }
+
+            result.map_err(|e| M5GoError::gpio("Unable to enter light sleep", e))?;
+            Ok(self.wake_cause())
+        }
+
+        fn deep_sleep(&mut self, wakeup: &WakeSources) -> ! {
+            if let Err(e) = self.configure(wakeup, true) {
+                log::warn!("Unable to configure the wake up sources: {:?}", e);
+            }
+            unsafe { esp_deep_sleep_start() }
+        }
+
+        #[allow(non_upper_case_globals)]
+        fn wake_cause(&self) -> WakeCause {
+            match unsafe { esp_sleep_get_wakeup_cause() } {
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED => WakeCause::PowerOn,
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeCause::Timer,
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1 => {
+                    let status = unsafe { esp_sleep_get_ext1_wakeup_status() };
+                    [Button::A, Button::B, Button::C]
+                        .into_iter()
+                        .find(|button| status & (1 << self.gpio(*button)) != 0)
+                        .map_or(WakeCause::Other, WakeCause::Button)
+                }
+                esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO => self
+                    .pressed_button()
+                    .map_or(WakeCause::Other, WakeCause::Button),
+                _ => WakeCause::Other,
+            }
+        }
+
+        #[allow(non_upper_case_globals)]
+        fn reset_reason(&self) -> ResetReason {
+            match unsafe { esp_reset_reason() } {
+                esp_reset_reason_t_ESP_RST_POWERON => ResetReason::PowerOn,
+                esp_reset_reason_t_ESP_RST_EXT => ResetReason::External,
+                esp_reset_reason_t_ESP_RST_SW => ResetReason::Software,
+                esp_reset_reason_t_ESP_RST_PANIC => ResetReason::Panic,
+                esp_reset_reason_t_ESP_RST_INT_WDT => ResetReason::InterruptWatchdog,
+                esp_reset_reason_t_ESP_RST_TASK_WDT => ResetReason::TaskWatchdog,
+                esp_reset_reason_t_ESP_RST_WDT => ResetReason::Watchdog,
+                esp_reset_reason_t_ESP_RST_DEEPSLEEP => ResetReason::DeepSleep,
+                esp_reset_reason_t_ESP_RST_BROWNOUT => ResetReason::Brownout,
+                _ => ResetReason::Unknown,
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! One-shot and periodic callbacks with millisecond resolution.
+//!
+//! The callbacks run in the timer task, not in the task that scheduled them. To act on the
+//! peripherals, post an [`Event`](crate::event::Event) from the callback and handle it in
+//! [`M5Go::run`](crate::M5Go::run).
+
+#[cfg(feature = "esp")]
+pub use esp::EspScheduler;
+
+#[cfg(feature = "esp")]
+mod esp {
+    use std::time::Duration;
+
+    use esp_idf_svc::timer::{EspTaskTimerService, EspTimer};
+
+    use crate::{
+        hal::{Scheduler, TimerHandle},
+        M5GoError,
+    };
+
+    /// Callbacks run by the `esp_timer` task
+    #[derive(Clone)]
+    pub struct EspScheduler {
+        service: EspTaskTimerService,
+    }
+
+    impl EspScheduler {
+        pub fn new() -> Result<Self, M5GoError> {
+            let service = EspTaskTimerService::new()
+                .map_err(|e| M5GoError::timer("Unable to start the timer service", e))?;
+            Ok(Self { service })
+        }
+
+        fn timer<F>(&self, callback: F) -> Result<EspTimer, M5GoError>
+        where
+            F: FnMut() + Send + 'static,
+        {
+            self.service
+                .timer(callback)
+                .map_err(|e| M5GoError::timer("Unable to create timer", e))
+        }
+    }
+
+    impl Scheduler for EspScheduler {
+        type Timer = EspTimer;
+
+        fn once<F>(&self, delay: Duration, callback: F) -> Result<EspTimer, M5GoError>
+        where
+            F: FnMut() + Send + 'static,
+        {
+            let timer = self.timer(callback)?;
+            timer
+                .after(delay)
+                .map_err(|e| M5GoError::timer("Unable to schedule timer", e))?;
+            Ok(timer)
+        }
+
+        fn every<F>(&self, period: Duration, callback: F) -> Result<EspTimer, M5GoError>
+        where
+            F: FnMut() + Send + 'static,
+        {
+            let timer = self.timer(callback)?;
+            timer
+                .every(period)
+                .map_err(|e| M5GoError::timer("Unable to schedule timer", e))?;
+            Ok(timer)
+        }
+
+        fn now(&self) -> Duration {
+            self.service.now()
+        }
+    }
+
+    impl TimerHandle for EspTimer {
+        fn cancel(&self) -> Result<bool, M5GoError> {
+            EspTimer::cancel(self).map_err(|e| M5GoError::timer("Unable to cancel timer", e))
+        }
+
+        fn is_scheduled(&self) -> Result<bool, M5GoError> {
+            EspTimer::is_scheduled(self)
+                .map_err(|e| M5GoError::timer("Unable to get the timer state", e))
+        }
+    }
+}
@@ -1,31 +1,176 @@
+#[cfg(feature = "esp")]
 use display_interface_spi::SPIInterfaceNoCS;
 use embedded_graphics::{
     image::{Image, ImageRawBE},
     mono_font::{MonoFont, MonoTextStyle},
     pixelcolor::Rgb565,
-    prelude::{DrawTarget, Point},
+    prelude::Point,
     text::{Alignment, Text},
     Drawable,
 };
+#[cfg(feature = "esp")]
+use embedded_graphics::{
+    prelude::{DrawTarget, OriginDimensions, Size},
+    primitives::Rectangle,
+    Pixel,
+};
+#[cfg(feature = "esp")]
 use esp_idf_hal::{
     delay::FreeRtos,
     gpio::{Gpio8, Output, OutputPin, PinDriver},
     spi::{SpiConfig, SpiDeviceDriver, SpiDriver, SPI2},
     units::Hertz,
 };
+#[cfg(feature = "esp")]
 use ili9341::{DisplaySize240x320, Ili9341};
 
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{hal::Display, M5GoError};
+
+#[cfg(feature = "esp")]
 pub type ScreenDriver<'a, DC, RST> = Ili9341<
     SPIInterfaceNoCS<SpiDeviceDriver<'a, SpiDriver<'a>>, PinDriver<'a, DC, Output>>,
     PinDriver<'a, RST, Output>,
 >;
 
-pub struct Screen<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> {
-    pub driver: ScreenDriver<'a, DC, RST>,
+/// The ILI9341 and its backlight pin
+#[cfg(feature = "esp")]
+pub struct EspDisplay<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> {
+    pub lcd: ScreenDriver<'a, DC, RST>,
     bl: PinDriver<'a, BL, Output>,
 }
 
-impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> Screen<'a, DC, RST, BL> {
+#[cfg(feature = "esp")]
+impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> OriginDimensions
+    for EspDisplay<'a, DC, RST, BL>
+{
+    fn size(&self) -> Size {
+        self.lcd.size()
+    }
+}
+
+#[cfg(feature = "esp")]
+impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> DrawTarget for EspDisplay<'a, DC, RST, BL> {
+    type Color = Rgb565;
+    type Error = <ScreenDriver<'a, DC, RST> as DrawTarget>::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.lcd.draw_iter(pixels)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.lcd.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.lcd.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.lcd.clear(color)
+    }
+}
+
+#[cfg(feature = "esp")]
+impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> Display for EspDisplay<'a, DC, RST, BL> {
+    fn set_backlight(&mut self, on: bool) -> Result<(), M5GoError> {
+        if on {
+            self.bl.set_high()
+        } else {
+            self.bl.set_low()
+        }
+        .map_err(|e| M5GoError::screen("Failed switching the backlight", e))
+    }
+
+    fn is_backlight_on(&self) -> bool {
+        self.bl.is_set_high()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScreenOrientation {
+    #[default]
+    Landscape,
+    LandscapeFlipped,
+    Portrait,
+    PortraitFlipped,
+}
+
+#[cfg(feature = "esp")]
+impl ScreenOrientation {
+    // The M5Stack panel is natively 320 * 240, so its MemoryAccessControl values differ from
+    // the ones the ili9341 crate uses
+    fn memory_access_control(self) -> u8 {
+        const MY: u8 = 0x80;
+        const MX: u8 = 0x40;
+        const MV: u8 = 0x20;
+        const BGR: u8 = 0x08;
+
+        match self {
+            ScreenOrientation::Landscape => BGR,
+            ScreenOrientation::LandscapeFlipped => MY | MX | BGR,
+            ScreenOrientation::Portrait => MV | MY | BGR,
+            ScreenOrientation::PortraitFlipped => MV | MX | BGR,
+        }
+    }
+
+    fn ili9341(self) -> ili9341::Orientation {
+        match self {
+            ScreenOrientation::Landscape => ili9341::Orientation::Landscape,
+            ScreenOrientation::LandscapeFlipped => ili9341::Orientation::LandscapeFlipped,
+            ScreenOrientation::Portrait => ili9341::Orientation::Portrait,
+            ScreenOrientation::PortraitFlipped => ili9341::Orientation::PortraitFlipped,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenConfig {
+    pub orientation: ScreenOrientation,
+    /// SPI clock in Hz
+    pub baudrate: u32,
+}
+
+impl ScreenConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn orientation(mut self, orientation: ScreenOrientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn baudrate(mut self, baudrate: u32) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+}
+
+impl Default for ScreenConfig {
+    fn default() -> Self {
+        Self {
+            orientation: ScreenOrientation::Landscape,
+            baudrate: 10 * 1000 * 1000,
+        }
+    }
+}
+
+/// The display is shared with the panic hook, which only draws when nobody else is
+pub struct Screen<D: Display> {
+    driver: Arc<Mutex<D>>,
+}
+
+#[cfg(feature = "esp")]
+impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> Screen<EspDisplay<'a, DC, RST, BL>> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<CS: OutputPin, SDO: OutputPin, SCLK: OutputPin>(
         cs: CS,
         sdo: SDO,
@@ -34,8 +179,9 @@ impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> Screen<'a, DC, RST, BL> {
         rst: RST,
         blk: BL,
         spi2: SPI2,
-    ) -> Self {
-        let spi_config = SpiConfig::new().baudrate(Hertz(10 * 1000 * 1000));
+        config: &ScreenConfig,
+    ) -> Result<Self, M5GoError> {
+        let spi_config = SpiConfig::new().baudrate(Hertz(config.baudrate));
 
         let lcd_spi_master = SpiDeviceDriver::new_single(
             spi2,
@@ -48,46 +194,79 @@ impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> Screen<'a, DC, RST, BL> {
             Some(cs),
             &spi_config,
         )
-        .expect("Creating screen spi master failed");
+        .map_err(|e| M5GoError::screen("Creating screen spi master failed", e))?;
 
-        let spi_display_interface =
-            SPIInterfaceNoCS::new(lcd_spi_master, PinDriver::output(dc).unwrap());
+        let dc = PinDriver::output(dc).map_err(|e| M5GoError::screen("Invalid DC pin", e))?;
+        let rst = PinDriver::output(rst).map_err(|e| M5GoError::screen("Invalid reset pin", e))?;
+        let bl =
+            PinDriver::output(blk).map_err(|e| M5GoError::screen("Invalid backlight pin", e))?;
+
+        let spi_display_interface = SPIInterfaceNoCS::new(lcd_spi_master, dc);
 
-        // Orientation Landscape for a 320 * 240 screen
         let mut lcd = Ili9341::new(
             spi_display_interface,
-            PinDriver::output(rst).unwrap(),
+            rst,
             &mut FreeRtos,
-            ili9341::Orientation::Landscape,
+            config.orientation.ili9341(),
             DisplaySize240x320,
         )
-        .expect("Failed to initialize LCD ILI9341.");
+        .map_err(|e| M5GoError::screen("Failed to initialize LCD ILI9341", e))?;
 
         lcd.command(ili9341::Command::DisplayInvertionOn, &[])
-            .expect("Failed to issue Display Invertion ON command");
-        lcd.command(ili9341::Command::MemoryAccessControl, &[0x00 | 0x08])
-            .expect("Failed to issue MemoryAccessControl command");
+            .map_err(|e| M5GoError::screen("Failed to issue Display Invertion ON command", e))?;
+        lcd.command(
+            ili9341::Command::MemoryAccessControl,
+            &[config.orientation.memory_access_control()],
+        )
+        .map_err(|e| M5GoError::screen("Failed to issue MemoryAccessControl command", e))?;
+
+        Ok(Self::from_display(EspDisplay { lcd, bl }))
+    }
+}
 
+impl<D: Display> Screen<D>
+where
+    D::Error: core::fmt::Debug,
+{
+    pub fn from_display(driver: D) -> Self {
         Self {
-            driver: lcd,
-            bl: PinDriver::output(blk).unwrap(),
+            driver: Arc::new(Mutex::new(driver)),
         }
     }
 
+    /// The display, to draw with embedded-graphics directly
+    pub fn driver(&self) -> MutexGuard<'_, D> {
+        self.driver.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    pub(crate) fn shared_driver(&self) -> Arc<Mutex<D>> {
+        self.driver.clone()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.driver().size().width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.driver().size().height
+    }
+
     pub fn is_on(&self) -> bool {
-        self.bl.is_set_high()
+        self.driver().is_backlight_on()
     }
 
-    pub fn turn_on(&mut self) {
-        self.bl.set_high().unwrap();
+    pub fn turn_on(&mut self) -> Result<(), M5GoError> {
+        self.driver().set_backlight(true)
     }
 
-    pub fn turn_off(&mut self) {
-        self.bl.set_low().unwrap();
+    pub fn turn_off(&mut self) -> Result<(), M5GoError> {
+        self.driver().set_backlight(false)
     }
 
-    pub fn fill_background(&mut self, color: Rgb565) {
-        self.driver.clear(color).expect("Failed setting background")
+    pub fn fill_background(&mut self, color: Rgb565) -> Result<(), M5GoError> {
+        self.driver()
+            .clear(color)
+            .map_err(|e| M5GoError::screen("Failed setting background", e))
     }
 
     pub fn draw_text(
@@ -97,19 +276,29 @@ impl<'a, DC: OutputPin, RST: OutputPin, BL: OutputPin> Screen<'a, DC, RST, BL> {
         alignment: Alignment,
         color: Rgb565,
         font: &MonoFont,
-    ) -> Point {
+    ) -> Result<Point, M5GoError> {
         let character_style = MonoTextStyle::new(font, color);
 
         let text_drawable = Text::with_alignment(text, position, character_style, alignment);
 
-        text_drawable
-            .draw(&mut self.driver)
-            .expect(format!("Draw text '{text}' in position {position} failed").as_str())
+        text_drawable.draw(&mut *self.driver()).map_err(|e| {
+            M5GoError::screen(
+                &format!("Draw text '{text}' in position {position} failed"),
+                e,
+            )
+        })
     }
 
-    pub fn draw_image(&mut self, data: &[u8], width: u32, position: Point) {
+    pub fn draw_image(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        position: Point,
+    ) -> Result<(), M5GoError> {
         let image_raw = ImageRawBE::<Rgb565>::new(data, width);
         let image = Image::new(&image_raw, position);
-        image.draw(&mut self.driver).expect("Failed drawing image");
+        image
+            .draw(&mut *self.driver())
+            .map_err(|e| M5GoError::screen("Failed drawing image", e))
     }
 }
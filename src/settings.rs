@@ -0,0 +1,341 @@
+//! Typed settings persisted across reboots, in the NVS or in a file on the host.
+//!
+//! The settings carry the version of their schema. When the firmware expects a newer version
+//! than the stored one, [`Settings::open`] runs the [`Migration`]s in between.
+//!
+//! ```ignore
+//! const VERSION: u16 = 2;
+//! const MIGRATIONS: &[Migration] = &[Migration {
+//!     version: 2,
+//!     // The volume used to be stored in percent
+//!     migrate: |settings| {
+//!         let volume = settings.get(keys::VOLUME)?.unwrap_or(100);
+//!         settings.set(keys::VOLUME, &((volume as u16 * 255 / 100) as u8))
+//!     },
+//! }];
+//!
+//! let mut settings = m5.settings(VERSION, MIGRATIONS)?;
+//! let brightness = settings.get_or(keys::LED_BRIGHTNESS, 255)?;
+//! ```
+
+use std::{fmt::Debug, marker::PhantomData, time::Duration};
+
+use crate::{
+    hal::{Platform, Storage},
+    M5Go, M5GoError,
+};
+
+const VERSION_KEY: &str = "schema_version";
+
+/// A value that can be stored as bytes
+pub trait SettingValue: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// `None` when the bytes do not hold a value of this type
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! integer_setting {
+    ($($type:ty),*) => {
+        $(
+            impl SettingValue for $type {
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    bytes.try_into().ok().map(<$type>::from_le_bytes)
+                }
+            }
+        )*
+    };
+}
+
+integer_setting!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl SettingValue for bool {
+    fn to_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl SettingValue for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+/// Stored in milliseconds
+impl SettingValue for Duration {
+    fn to_bytes(&self) -> Vec<u8> {
+        (self.as_millis() as u64).to_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        u64::from_bytes(bytes).map(Duration::from_millis)
+    }
+}
+
+impl SettingValue for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl SettingValue for Vec<i16> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let pairs = bytes.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return None;
+        }
+        Some(
+            pairs
+                .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                .collect(),
+        )
+    }
+}
+
+/// The name of a setting and the type of its value, names are at most 15 characters long
+pub struct Key<T> {
+    name: &'static str,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> Key<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: PhantomData,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Key<T> {}
+
+impl<T> Debug for Key<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Key").field(&self.name).finish()
+    }
+}
+
+/// Settings of the peripherals handled by this crate
+pub mod keys {
+    use std::time::Duration;
+
+    use super::Key;
+
+    pub const LED_BRIGHTNESS: Key<u8> = Key::new("led_brightness");
+    pub const VOLUME: Key<u8> = Key::new("volume");
+    pub const BLE_NAME: Key<String> = Key::new("ble_name");
+    /// Inactivity before the screen turns off
+    pub const SCREEN_TIMEOUT: Key<Duration> = Key::new("screen_timeout");
+    /// Accelerometer then gyroscope offsets of the IMU
+    pub const IMU_CALIBRATION: Key<Vec<i16>> = Key::new("imu_calibration");
+}
+
+/// Upgrades the settings from the previous version to `version`
+pub struct Migration {
+    pub version: u16,
+    pub migrate: fn(&mut Settings<&mut dyn Storage>) -> Result<(), M5GoError>,
+}
+
+pub struct Settings<S: Storage> {
+    storage: S,
+    version: u16,
+}
+
+impl<S: Storage> Settings<S> {
+    /// Open the settings at `version`, migrating older ones. Settings written by a newer
+    /// firmware are refused rather than misread.
+    pub fn open(mut storage: S, version: u16, migrations: &[Migration]) -> Result<Self, M5GoError> {
+        let stored = match storage.read(VERSION_KEY)? {
+            Some(bytes) => Some(
+                u16::from_bytes(&bytes)
+                    .ok_or_else(|| M5GoError::storage("Invalid schema version", bytes))?,
+            ),
+            None => None,
+        };
+
+        match stored {
+            Some(stored) if stored > version => {
+                return Err(M5GoError::storage(
+                    "Settings written by a newer schema",
+                    stored,
+                ));
+            }
+            Some(stored) if stored < version => {
+                let mut pending = migrations
+                    .iter()
+                    .filter(|migration| migration.version > stored && migration.version <= version)
+                    .collect::<Vec<_>>();
+                pending.sort_by_key(|migration| migration.version);
+
+                let mut settings = Settings {
+                    storage: &mut storage as &mut dyn Storage,
+                    version: stored,
+                };
+                for migration in pending {
+                    (migration.migrate)(&mut settings)?;
+                    settings.write_version(migration.version)?;
+                }
+                settings.write_version(version)?;
+            }
+            Some(_) => (),
+            // A blank store, nothing to migrate
+            None => storage.write(VERSION_KEY, &version.to_bytes())?,
+        }
+
+        Ok(Self { storage, version })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// `None` when the setting was never set
+    pub fn get<T: SettingValue>(&self, key: Key<T>) -> Result<Option<T>, M5GoError> {
+        match self.storage.read(key.name)? {
+            Some(bytes) => T::from_bytes(&bytes)
+                .map(Some)
+                .ok_or_else(|| M5GoError::storage("Unexpected value type", key)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or<T: SettingValue>(&self, key: Key<T>, default: T) -> Result<T, M5GoError> {
+        Ok(self.get(key)?.unwrap_or(default))
+    }
+
+    pub fn set<T: SettingValue>(&mut self, key: Key<T>, value: &T) -> Result<(), M5GoError> {
+        self.storage.write(key.name, &value.to_bytes())
+    }
+
+    /// Returns whether the setting was set
+    pub fn remove<T>(&mut self, key: Key<T>) -> Result<bool, M5GoError> {
+        self.storage.remove(key.name)
+    }
+
+    pub fn release(self) -> S {
+        self.storage
+    }
+
+    fn write_version(&mut self, version: u16) -> Result<(), M5GoError> {
+        self.storage.write(VERSION_KEY, &version.to_bytes())?;
+        self.version = version;
+        Ok(())
+    }
+}
+
+impl<P: Platform> M5Go<P> {
+    /// Typed access to the storage, see [`Settings::open`]
+    pub fn settings(
+        &mut self,
+        version: u16,
+        migrations: &[Migration],
+    ) -> Result<Settings<&mut P::Storage>, M5GoError> {
+        Settings::open(&mut self.storage, version, migrations)
+    }
+}
+
+#[cfg(feature = "esp")]
+pub use esp::EspStorage;
+
+#[cfg(feature = "esp")]
+mod esp {
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    use embedded_svc::storage::RawStorage;
+    use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+    use crate::{
+        hal::{Storage, MAX_VALUE_LEN},
+        M5GoError,
+    };
+
+    const NAMESPACE: &str = "m5go";
+
+    /// The `m5go` namespace of the default NVS partition, the clones share the handle
+    #[derive(Clone)]
+    pub struct EspStorage {
+        partition: EspDefaultNvsPartition,
+        nvs: Arc<Mutex<EspNvs<NvsDefault>>>,
+    }
+
+    impl EspStorage {
+        pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, M5GoError> {
+            let nvs = EspNvs::new(partition.clone(), NAMESPACE, true)
+                .map_err(|e| M5GoError::storage("Unable to open the NVS namespace", e))?;
+            Ok(Self {
+                partition,
+                nvs: Arc::new(Mutex::new(nvs)),
+            })
+        }
+
+        fn nvs(&self) -> MutexGuard<'_, EspNvs<NvsDefault>> {
+            self.nvs.lock().unwrap_or_else(|p| p.into_inner())
+        }
+
+        /// The partition can only be taken once, other users such as BLE get it from here
+        pub fn partition(&self) -> EspDefaultNvsPartition {
+            self.partition.clone()
+        }
+    }
+
+    impl Storage for EspStorage {
+        fn read(&self, key: &str) -> Result<Option<Vec<u8>>, M5GoError> {
+            let mut buffer = [0; MAX_VALUE_LEN];
+            self.nvs()
+                .get_raw(key, &mut buffer)
+                .map(|value| value.map(|value| value.to_vec()))
+                .map_err(|e| M5GoError::storage("Read failed", e))
+        }
+
+        fn write(&mut self, key: &str, value: &[u8]) -> Result<(), M5GoError> {
+            // It could be written but not read back
+            if value.len() > MAX_VALUE_LEN {
+                return Err(M5GoError::storage("Value too long", value.len()));
+            }
+            self.nvs()
+                .set_raw(key, value)
+                .map(|_| ())
+                .map_err(|e| M5GoError::storage("Write failed", e))
+        }
+
+        fn remove(&mut self, key: &str) -> Result<bool, M5GoError> {
+            self.nvs()
+                .remove(key)
+                .map_err(|e| M5GoError::storage("Remove failed", e))
+        }
+    }
+}
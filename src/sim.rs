@@ -0,0 +1,987 @@
+//! Host backend used when the crate is built with the `sim` feature.
+//!
+//! Every peripheral implements the [`hal`](crate::hal) traits so that application code can run
+//! unchanged on Linux, in CI for example.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU16, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use embedded_graphics::{
+    pixelcolor::{Rgb565, Rgb888},
+    prelude::{DrawTarget, OriginDimensions, Point, RgbColor, Size},
+    Pixel,
+};
+use log::{info, warn};
+use smart_leds::RGB8;
+
+use crate::{
+    board::{BoardProfile, M5GoBoard},
+    buttons::Buttons,
+    event::EventQueue,
+    hal::{
+        AnalogInput, ButtonInput, ButtonInterrupt, DigitalIo, Display, I2cBus, LedStrip, Platform,
+        PowerManagement, Scheduler, SerialPort, Storage, TimerHandle, ToneOutput, MAX_VALUE_LEN,
+    },
+    i2c::BusRecovery,
+    leds::Leds,
+    mac::MacAddress,
+    navigation::Navigation,
+    ports::{PortA, PortB, PortC},
+    power::{Button, ResetReason, WakeCause, WakeSources},
+    screen::Screen,
+    M5Go, M5GoError,
+};
+
+/// The peripherals of an M5Go simulated on the host
+pub struct SimPlatform;
+
+impl Platform for SimPlatform {
+    type Power = SimPower;
+    type Scheduler = SimScheduler;
+    type Storage = SimStorage;
+    type Display = SimDisplay;
+    type LedStrip = SimLeds;
+    type Tone = SimSpeaker;
+    type ButtonA = SimButton;
+    type ButtonB = SimButton;
+    type ButtonC = SimButton;
+    type PortA = SimI2c;
+    type PortB = SimAdc;
+    type PortC = SimSerial;
+}
+
+impl M5Go<SimPlatform> {
+    pub fn new() -> Result<Self, M5GoError> {
+        Self::with_board(M5GoBoard)
+    }
+
+    /// Simulate another core, only the peripherals it has are set up
+    pub fn with_board<B: BoardProfile>(board: B) -> Result<Self, M5GoError> {
+        let pins = board.pins();
+        let (button_a, button_b, button_c) = Default::default();
+        let power = SimPower::new(&button_a, &button_b, &button_c);
+
+        Ok(Self {
+            button_a,
+            button_b,
+            button_c,
+            buttons: Buttons::default(),
+            navigation: Navigation::default(),
+            leds: pins
+                .leds
+                .map(|bars| Leds::from_strip(SimLeds::new(), bars.count)),
+            screen: Some(Screen::from_display(SimDisplay::new())),
+            port_a: Some(PortA::new(SimI2c::default())),
+            port_b: pins.port_b.then(|| PortB::new(SimAdc::default())),
+            port_c: pins.port_c.then(|| PortC::new(SimSerial::default())),
+            speaker: Some(SimSpeaker::new()),
+            power,
+            scheduler: SimScheduler::default(),
+            storage: SimStorage::default(),
+            mac: MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
+            bt_mac: MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x03]),
+            imu: board.features().imu,
+            events: EventQueue::new(),
+            watchdog: None,
+        })
+    }
+}
+
+pub const SCREEN_WIDTH: u32 = 320;
+pub const SCREEN_HEIGHT: u32 = 240;
+
+/// An in-memory framebuffer standing in for the ILI9341 screen
+pub struct SimDisplay {
+    framebuffer: Vec<Rgb565>,
+    bl: bool,
+}
+
+impl SimDisplay {
+    pub fn new() -> Self {
+        Self {
+            framebuffer: vec![Rgb565::BLACK; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+            bl: false,
+        }
+    }
+
+    /// The color of the pixel at `point`, if it is on screen
+    pub fn pixel(&self, point: Point) -> Option<Rgb565> {
+        Self::index(point).map(|index| self.framebuffer[index])
+    }
+
+    /// Dump the framebuffer to a PNG file
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), M5GoError> {
+        let file = BufWriter::new(
+            File::create(path).map_err(|e| M5GoError::screen("Unable to create the PNG", e))?,
+        );
+
+        let mut encoder = png::Encoder::new(file, SCREEN_WIDTH, SCREEN_HEIGHT);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data = self
+            .framebuffer
+            .iter()
+            .flat_map(|color| {
+                let color = Rgb888::from(*color);
+                [color.r(), color.g(), color.b()]
+            })
+            .collect::<Vec<u8>>();
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(|e| M5GoError::screen("Unable to encode the PNG", e))
+    }
+
+    fn index(point: Point) -> Option<usize> {
+        let (x, y) = (u32::try_from(point.x).ok()?, u32::try_from(point.y).ok()?);
+        (x < SCREEN_WIDTH && y < SCREEN_HEIGHT).then(|| (y * SCREEN_WIDTH + x) as usize)
+    }
+}
+
+impl Default for SimDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for SimDisplay {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+}
+
+impl DrawTarget for SimDisplay {
+    type Color = Rgb565;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(index) = Self::index(point) {
+                self.framebuffer[index] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for SimDisplay {
+    fn set_backlight(&mut self, on: bool) -> Result<(), M5GoError> {
+        self.bl = on;
+        Ok(())
+    }
+
+    fn is_backlight_on(&self) -> bool {
+        self.bl
+    }
+}
+
+/// A led strip that records every displayed frame
+pub struct SimLeds {
+    frames: Vec<Vec<RGB8>>,
+}
+
+impl SimLeds {
+    pub fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+
+    /// The colors that were last displayed
+    pub fn colors(&self) -> &[RGB8] {
+        self.frames.last().map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Every frame displayed so far, oldest first
+    pub fn frames(&self) -> &[Vec<RGB8>] {
+        &self.frames
+    }
+}
+
+impl Default for SimLeds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LedStrip for SimLeds {
+    fn write_pixels(&mut self, pixels: &[RGB8]) -> Result<(), M5GoError> {
+        self.frames.push(pixels.to_vec());
+        Ok(())
+    }
+}
+
+type EdgeCallback = Box<dyn FnMut(bool) + Send>;
+
+/// A front button, active low like the real GPIO
+#[derive(Clone, Default)]
+pub struct SimButton {
+    pressed: Arc<AtomicBool>,
+    /// Stands for the interrupt handler, called from the task changing the level
+    on_edge: Arc<Mutex<Option<EdgeCallback>>>,
+}
+
+impl SimButton {
+    pub fn is_low(&self) -> bool {
+        self.pressed.load(Ordering::SeqCst)
+    }
+
+    pub fn is_high(&self) -> bool {
+        !self.is_low()
+    }
+
+    pub fn press(&self) {
+        self.set(true);
+    }
+
+    pub fn release(&self) {
+        self.set(false);
+    }
+
+    fn set(&self, pressed: bool) {
+        if self.pressed.swap(pressed, Ordering::SeqCst) == pressed {
+            return;
+        }
+        if let Some(callback) = self.on_edge.lock().unwrap().as_mut() {
+            callback(pressed);
+        }
+    }
+}
+
+impl ButtonInput for SimButton {
+    fn is_pressed(&self) -> bool {
+        self.is_low()
+    }
+}
+
+impl ButtonInterrupt for SimButton {
+    unsafe fn subscribe_edges<F>(&mut self, callback: F) -> Result<(), M5GoError>
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        *self.on_edge.lock().unwrap() = Some(Box::new(callback));
+        Ok(())
+    }
+
+    fn unsubscribe_edges(&mut self) -> Result<(), M5GoError> {
+        *self.on_edge.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+/// Drives the simulated buttons from a script
+///
+/// Each line has the form `<delay_ms> <A|B|C> <press|release>`, the delay being counted from the
+/// previous line. Empty lines and lines starting with `#` are ignored.
+pub struct SimInput {
+    buttons: [SimButton; 3],
+}
+
+impl SimInput {
+    pub fn new(button_a: &SimButton, button_b: &SimButton, button_c: &SimButton) -> Self {
+        Self {
+            buttons: [button_a.clone(), button_b.clone(), button_c.clone()],
+        }
+    }
+
+    /// Play the script in a background thread
+    pub fn run_script<R: BufRead + Send + 'static>(self, script: R) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for line in script.lines() {
+                let Ok(line) = line else { break };
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                match self.parse_line(line) {
+                    Some((delay, button, pressed)) => {
+                        thread::sleep(delay);
+                        if pressed {
+                            button.press();
+                        } else {
+                            button.release();
+                        }
+                    }
+                    None => warn!("Invalid input line: {line:?}"),
+                }
+            }
+        })
+    }
+
+    /// Read the script from stdin, one line at a time
+    pub fn run_stdin(self) -> JoinHandle<()> {
+        self.run_script(std::io::BufReader::new(std::io::stdin()))
+    }
+
+    fn parse_line(&self, line: &str) -> Option<(Duration, &SimButton, bool)> {
+        let mut words = line.split_whitespace();
+        let delay = Duration::from_millis(words.next()?.parse().ok()?);
+        let button = match words.next()? {
+            "A" | "a" => &self.buttons[0],
+            "B" | "b" => &self.buttons[1],
+            "C" | "c" => &self.buttons[2],
+            _ => return None,
+        };
+        let pressed = match words.next()? {
+            "press" => true,
+            "release" => false,
+            _ => return None,
+        };
+        Some((delay, button, pressed))
+    }
+}
+
+/// A speaker that logs the tones it plays
+#[derive(Default)]
+pub struct SimSpeaker {
+    playing: Option<(u32, Instant)>,
+    tones: Vec<(u32, Duration)>,
+}
+
+impl SimSpeaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The tones played so far with their duration
+    pub fn tones(&self) -> &[(u32, Duration)] {
+        &self.tones
+    }
+}
+
+impl ToneOutput for SimSpeaker {
+    fn play(&mut self, freq: u32) -> Result<(), M5GoError> {
+        self.stop()?;
+        info!("Speaker: playing {} Hz", freq);
+        self.playing = Some((freq, Instant::now()));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), M5GoError> {
+        if let Some((freq, started)) = self.playing.take() {
+            let duration = started.elapsed();
+            info!("Speaker: stopped {} Hz after {:?}", freq, duration);
+            self.tones.push((freq, duration));
+        }
+        Ok(())
+    }
+
+    fn playing(&self) -> Option<u32> {
+        self.playing.map(|(freq, _)| freq)
+    }
+}
+
+/// Sleeps by blocking the calling thread, a button press from the script wakes it up
+pub struct SimPower {
+    buttons: [SimButton; 3],
+    wake_cause: WakeCause,
+    reset_reason: ResetReason,
+}
+
+impl SimPower {
+    pub fn new(button_a: &SimButton, button_b: &SimButton, button_c: &SimButton) -> Self {
+        Self {
+            buttons: [button_a.clone(), button_b.clone(), button_c.clone()],
+            wake_cause: WakeCause::PowerOn,
+            reset_reason: ResetReason::PowerOn,
+        }
+    }
+
+    /// Pretend the chip started for another reason, to exercise the crash reports
+    pub fn set_reset_reason(&mut self, reason: ResetReason) {
+        self.reset_reason = reason;
+    }
+
+    fn wait(&self, wakeup: &WakeSources) -> WakeCause {
+        let started = Instant::now();
+        loop {
+            if let Some(button) = wakeup.button {
+                let index = match button {
+                    Button::A => 0,
+                    Button::B => 1,
+                    Button::C => 2,
+                };
+                if self.buttons[index].is_low() {
+                    return WakeCause::Button(button);
+                }
+            }
+            if let Some(duration) = wakeup.timer {
+                if started.elapsed() >= duration {
+                    return WakeCause::Timer;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl PowerManagement for SimPower {
+    fn light_sleep(&mut self, wakeup: &WakeSources) -> Result<WakeCause, M5GoError> {
+        info!("Light sleep until {:?}", wakeup);
+        self.wake_cause = self.wait(wakeup);
+        Ok(self.wake_cause)
+    }
+
+    fn deep_sleep(&mut self, wakeup: &WakeSources) -> ! {
+        info!("Deep sleep until {:?}", wakeup);
+        let cause = self.wait(wakeup);
+        info!("Woken up by {:?}, the device reboots", cause);
+        std::process::exit(0)
+    }
+
+    fn wake_cause(&self) -> WakeCause {
+        self.wake_cause
+    }
+
+    fn reset_reason(&self) -> ResetReason {
+        self.reset_reason
+    }
+}
+
+type Callback = Box<dyn FnMut() + Send>;
+
+struct SimTimerEntry {
+    id: u64,
+    deadline: Duration,
+    period: Option<Duration>,
+    /// Taken out while the callback runs
+    callback: Option<Callback>,
+}
+
+#[derive(Default)]
+struct SimClock {
+    now: Duration,
+    next_id: u64,
+    timers: Vec<SimTimerEntry>,
+}
+
+/// Timers driven by a fake clock, the callbacks only run when the clock is advanced
+#[derive(Clone, Default)]
+pub struct SimScheduler {
+    clock: Arc<Mutex<SimClock>>,
+}
+
+impl SimScheduler {
+    /// Move the clock forward, running every callback due meanwhile in deadline order
+    pub fn advance(&self, duration: Duration) {
+        let target = self.now() + duration;
+        loop {
+            let Ok(mut clock) = self.clock.lock() else {
+                return;
+            };
+            let Some(index) = clock
+                .timers
+                .iter()
+                .enumerate()
+                .filter(|(_, timer)| timer.callback.is_some() && timer.deadline <= target)
+                .min_by_key(|(_, timer)| timer.deadline)
+                .map(|(index, _)| index)
+            else {
+                clock.now = target;
+                return;
+            };
+
+            let timer = &mut clock.timers[index];
+            let id = timer.id;
+            let now = timer.deadline;
+            let mut callback = match timer.period {
+                Some(period) => {
+                    timer.deadline += period;
+                    timer.callback.take()
+                }
+                None => clock.timers.remove(index).callback,
+            };
+            clock.now = now;
+            drop(clock);
+
+            // The lock is released so that the callback can schedule or cancel timers
+            if let Some(callback) = &mut callback {
+                callback();
+            }
+
+            if let Ok(mut clock) = self.clock.lock() {
+                if let Some(timer) = clock.timers.iter_mut().find(|timer| timer.id == id) {
+                    timer.callback = callback;
+                }
+            }
+        }
+    }
+
+    fn schedule(
+        &self,
+        delay: Duration,
+        period: Option<Duration>,
+        callback: Callback,
+    ) -> Result<SimTimer, M5GoError> {
+        let mut clock = self
+            .clock
+            .lock()
+            .map_err(|e| M5GoError::timer("Clock poisoned", e))?;
+        let id = clock.next_id;
+        clock.next_id += 1;
+        let deadline = clock.now + delay;
+        clock.timers.push(SimTimerEntry {
+            id,
+            deadline,
+            period,
+            callback: Some(callback),
+        });
+        Ok(SimTimer {
+            id,
+            clock: Arc::clone(&self.clock),
+        })
+    }
+}
+
+impl Scheduler for SimScheduler {
+    type Timer = SimTimer;
+
+    fn once<F>(&self, delay: Duration, callback: F) -> Result<SimTimer, M5GoError>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.schedule(delay, None, Box::new(callback))
+    }
+
+    fn every<F>(&self, period: Duration, callback: F) -> Result<SimTimer, M5GoError>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        if period.is_zero() {
+            return Err(M5GoError::timer("Invalid period", period));
+        }
+        self.schedule(period, Some(period), Box::new(callback))
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.lock().map(|clock| clock.now).unwrap_or_default()
+    }
+}
+
+pub struct SimTimer {
+    id: u64,
+    clock: Arc<Mutex<SimClock>>,
+}
+
+impl TimerHandle for SimTimer {
+    fn cancel(&self) -> Result<bool, M5GoError> {
+        let mut clock = self
+            .clock
+            .lock()
+            .map_err(|e| M5GoError::timer("Clock poisoned", e))?;
+        let count = clock.timers.len();
+        clock.timers.retain(|timer| timer.id != self.id);
+        Ok(clock.timers.len() != count)
+    }
+
+    fn is_scheduled(&self) -> Result<bool, M5GoError> {
+        let clock = self
+            .clock
+            .lock()
+            .map_err(|e| M5GoError::timer("Clock poisoned", e))?;
+        Ok(clock.timers.iter().any(|timer| timer.id == self.id))
+    }
+}
+
+impl Drop for SimTimer {
+    fn drop(&mut self) {
+        self.cancel().ok();
+    }
+}
+
+/// Key-value storage kept in memory, and in a file when opened with [`SimStorage::open`]
+///
+/// The clones share the values.
+#[derive(Clone, Default)]
+pub struct SimStorage {
+    path: Option<PathBuf>,
+    values: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl SimStorage {
+    /// Load the values from `path` if it exists, every write saves them back there. The file
+    /// holds one `key=hex` line per value.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, M5GoError> {
+        let path = path.as_ref().to_path_buf();
+        let mut values = BTreeMap::new();
+
+        if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| M5GoError::storage("Unable to read the storage file", e))?;
+            for line in content.lines().filter(|line| !line.is_empty()) {
+                let (key, hex) = line
+                    .split_once('=')
+                    .ok_or_else(|| M5GoError::storage("Invalid line", line))?;
+                let value = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| {
+                        hex.get(i..i + 2)
+                            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| M5GoError::storage("Invalid value", line))?;
+                values.insert(key.to_string(), value);
+            }
+        }
+
+        Ok(Self {
+            path: Some(path),
+            values: Arc::new(Mutex::new(values)),
+        })
+    }
+
+    fn values(&self) -> MutexGuard<'_, BTreeMap<String, Vec<u8>>> {
+        self.values.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn save(&self, values: &BTreeMap<String, Vec<u8>>) -> Result<(), M5GoError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content: String = values
+            .iter()
+            .map(|(key, value)| {
+                let hex: String = value.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("{}={}\n", key, hex)
+            })
+            .collect();
+        std::fs::write(path, content)
+            .map_err(|e| M5GoError::storage("Unable to write the storage file", e))
+    }
+}
+
+impl Storage for SimStorage {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, M5GoError> {
+        Ok(self.values().get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), M5GoError> {
+        if key.len() > 15 {
+            return Err(M5GoError::storage("Key too long", key));
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(M5GoError::storage("Value too long", value.len()));
+        }
+        let mut values = self.values();
+        values.insert(key.to_string(), value.to_vec());
+        self.save(&values)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, M5GoError> {
+        let mut values = self.values();
+        let removed = values.remove(key).is_some();
+        self.save(&values)?;
+        Ok(removed)
+    }
+}
+
+/// An I2C bus with scripted devices, reading from an unknown address fails like a NACK would
+#[derive(Default)]
+pub struct SimI2c {
+    devices: HashMap<u8, VecDeque<u8>>,
+    writes: Vec<(u8, Vec<u8>)>,
+    /// Whether a device holds SDA low, failing every operation
+    stuck: Arc<AtomicBool>,
+}
+
+impl SimI2c {
+    /// Add a device answering `response` to the next reads
+    pub fn add_device(&mut self, address: u8, response: &[u8]) {
+        self.devices
+            .entry(address)
+            .or_default()
+            .extend(response.iter().copied());
+    }
+
+    /// Every write so far with its address, oldest first
+    pub fn writes(&self) -> &[(u8, Vec<u8>)] {
+        &self.writes
+    }
+
+    /// Pretend a device holds SDA low, until the bus is recovered
+    pub fn hold_sda(&self) {
+        self.stuck.store(true, Ordering::SeqCst);
+    }
+
+    pub fn recovery(&self) -> SimBusRecovery {
+        SimBusRecovery {
+            stuck: Arc::clone(&self.stuck),
+        }
+    }
+
+    fn check_bus(&self) -> Result<(), M5GoError> {
+        if self.stuck.load(Ordering::SeqCst) {
+            return Err(M5GoError::I2c("SDA held low".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Releases the SDA line held by [`SimI2c::hold_sda`]
+pub struct SimBusRecovery {
+    stuck: Arc<AtomicBool>,
+}
+
+impl BusRecovery for SimBusRecovery {
+    fn recover(&mut self) -> Result<bool, M5GoError> {
+        Ok(self.stuck.swap(false, Ordering::SeqCst))
+    }
+}
+
+impl I2cBus for SimI2c {
+    fn write_to(&mut self, address: u8, bytes: &[u8], _timeout: u32) -> Result<(), M5GoError> {
+        self.check_bus()?;
+        if !self.devices.contains_key(&address) {
+            return Err(M5GoError::I2c(format!(
+                "No device at address {address:#04x}"
+            )));
+        }
+        self.writes.push((address, bytes.to_vec()));
+        Ok(())
+    }
+
+    fn read_from(
+        &mut self,
+        address: u8,
+        buffer: &mut [u8],
+        _timeout: u32,
+    ) -> Result<(), M5GoError> {
+        self.check_bus()?;
+        let response = self
+            .devices
+            .get_mut(&address)
+            .ok_or_else(|| M5GoError::I2c(format!("No device at address {address:#04x}")))?;
+        for byte in buffer {
+            *byte = response.pop_front().unwrap_or(0xFF);
+        }
+        Ok(())
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+        timeout: u32,
+    ) -> Result<(), M5GoError> {
+        self.write_to(address, bytes, timeout)?;
+        self.read_from(address, buffer, timeout)
+    }
+}
+
+/// An ADC input whose value is set by the test, e.g. the angle unit on Port B, and the GPIO of the
+/// port
+#[derive(Clone, Default)]
+pub struct SimAdc {
+    value: Arc<AtomicU16>,
+    level: Arc<AtomicBool>,
+}
+
+impl SimAdc {
+    pub fn set(&self, value: u16) {
+        self.value.store(value, Ordering::SeqCst);
+    }
+
+    /// Drive the GPIO from the outside
+    pub fn set_gpio(&self, high: bool) {
+        self.level.store(high, Ordering::SeqCst);
+    }
+}
+
+impl DigitalIo for SimAdc {
+    fn set_level(&mut self, high: bool) -> Result<(), M5GoError> {
+        self.level.store(high, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn is_high(&self) -> bool {
+        self.level.load(Ordering::SeqCst)
+    }
+}
+
+impl AnalogInput for SimAdc {
+    fn read_raw(&mut self) -> Result<u16, M5GoError> {
+        Ok(self.value.load(Ordering::SeqCst))
+    }
+}
+
+/// A serial port keeping what was written and returning what was fed to it
+#[derive(Clone, Default)]
+pub struct SimSerial {
+    rx: Arc<Mutex<VecDeque<u8>>>,
+    tx: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SimSerial {
+    /// Queue bytes as if they were received on the port
+    pub fn feed(&self, bytes: &[u8]) {
+        if let Ok(mut rx) = self.rx.lock() {
+            rx.extend(bytes.iter().copied());
+        }
+    }
+
+    /// Everything written to the port so far
+    pub fn written(&self) -> Vec<u8> {
+        self.tx.lock().map(|tx| tx.clone()).unwrap_or_default()
+    }
+}
+
+impl SerialPort for SimSerial {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, M5GoError> {
+        let mut tx = self
+            .tx
+            .lock()
+            .map_err(|e| M5GoError::uart("Serial port poisoned", e))?;
+        tx.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8], _timeout: u32) -> Result<usize, M5GoError> {
+        let mut rx = self
+            .rx
+            .lock()
+            .map_err(|e| M5GoError::uart("Serial port poisoned", e))?;
+        let count = buffer.len().min(rx.len());
+        for (byte, received) in buffer.iter_mut().zip(rx.drain(..count)) {
+            *byte = received;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::i2c::Operation;
+
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// A callback recording the clock each time it runs, with the list it records in
+    fn recorder(scheduler: &SimScheduler) -> (Arc<Mutex<Vec<Duration>>>, impl FnMut() + Send) {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let callback = {
+            let fired = Arc::clone(&fired);
+            let scheduler = scheduler.clone();
+            move || fired.lock().unwrap().push(scheduler.now())
+        };
+        (fired, callback)
+    }
+
+    #[test]
+    fn once_fires_on_advance() {
+        let scheduler = SimScheduler::default();
+        let (fired, callback) = recorder(&scheduler);
+        let timer = scheduler.once(ms(100), callback).unwrap();
+
+        scheduler.advance(ms(99));
+        assert!(fired.lock().unwrap().is_empty());
+        assert!(timer.is_scheduled().unwrap());
+
+        scheduler.advance(ms(1));
+        scheduler.advance(ms(500));
+        assert_eq!(*fired.lock().unwrap(), [ms(100)]);
+        assert!(!timer.is_scheduled().unwrap());
+        assert_eq!(scheduler.now(), ms(600));
+    }
+
+    #[test]
+    fn every_fires_on_advance() {
+        let scheduler = SimScheduler::default();
+        let (fired, callback) = recorder(&scheduler);
+        let _timer = scheduler.every(ms(100), callback).unwrap();
+
+        scheduler.advance(ms(150));
+        assert_eq!(*fired.lock().unwrap(), [ms(100)]);
+        scheduler.advance(ms(50));
+        assert_eq!(*fired.lock().unwrap(), [ms(100), ms(200)]);
+    }
+
+    #[test]
+    fn every_catches_up_after_a_large_advance() {
+        let scheduler = SimScheduler::default();
+        let (fired, callback) = recorder(&scheduler);
+        let _timer = scheduler.every(ms(100), callback).unwrap();
+
+        scheduler.advance(ms(350));
+        assert_eq!(*fired.lock().unwrap(), [ms(100), ms(200), ms(300)]);
+        assert_eq!(scheduler.now(), ms(350));
+    }
+
+    #[test]
+    fn every_rejects_a_zero_period() {
+        let scheduler = SimScheduler::default();
+        assert!(scheduler.every(Duration::ZERO, || ()).is_err());
+    }
+
+    #[test]
+    fn drop_cancels() {
+        let scheduler = SimScheduler::default();
+        let (fired, callback) = recorder(&scheduler);
+        let timer = scheduler.every(ms(100), callback).unwrap();
+
+        scheduler.advance(ms(100));
+        drop(timer);
+        scheduler.advance(ms(1000));
+        assert_eq!(*fired.lock().unwrap(), [ms(100)]);
+    }
+
+    #[test]
+    fn cancel_reports_whether_scheduled() {
+        let scheduler = SimScheduler::default();
+        let timer = scheduler.once(ms(100), || ()).unwrap();
+        assert!(timer.cancel().unwrap());
+        assert!(!timer.cancel().unwrap());
+        assert!(!timer.is_scheduled().unwrap());
+    }
+
+    #[test]
+    fn i2c_transaction_merges_operations_of_a_direction() {
+        let mut bus = SimI2c::default();
+        bus.add_device(0x44, &[1, 2, 3]);
+        let (mut first, mut second) = ([0; 1], [0; 2]);
+        bus.transaction(
+            0x44,
+            &mut [
+                Operation::Write(&[0x24]),
+                Operation::Write(&[0x00]),
+                Operation::Read(&mut first),
+                Operation::Read(&mut second),
+            ],
+            0,
+        )
+        .unwrap();
+        assert_eq!(bus.writes(), [(0x44, vec![0x24, 0x00])]);
+        assert_eq!((first, second), ([1], [2, 3]));
+    }
+
+    #[test]
+    fn i2c_transaction_rejects_a_write_after_a_read() {
+        let mut bus = SimI2c::default();
+        bus.add_device(0x44, &[1]);
+        let mut buffer = [0; 1];
+        let result = bus.transaction(
+            0x44,
+            &mut [Operation::Read(&mut buffer), Operation::Write(&[0x24])],
+            0,
+        );
+        assert!(result.is_err());
+        assert!(bus.writes().is_empty());
+    }
+}
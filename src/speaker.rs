@@ -7,6 +7,8 @@ use esp_idf_hal::{
     units::Hertz,
 };
 
+use crate::{hal::ToneOutput, M5GoError};
+
 pub struct Speaker<P: OutputPin, C: LedcChannel, T: LedcTimer>
 where
     C: Peripheral<P = C>,
@@ -15,6 +17,7 @@ where
     pin: P,
     channel: C,
     timer: T,
+    tone: Option<(u32, LedcDriver<'static>)>,
 }
 
 impl<P: OutputPin, C: LedcChannel, T: LedcTimer> Speaker<P, C, T>
@@ -27,6 +30,7 @@ where
             pin,
             channel,
             timer,
+            tone: None,
         }
     }
 
@@ -47,3 +51,42 @@ where
             .ok()
     }
 }
+
+impl<P: OutputPin, C: LedcChannel, T: LedcTimer> ToneOutput for Speaker<P, C, T>
+where
+    C: Peripheral<P = C> + 'static,
+    T: Peripheral<P = T> + 'static,
+{
+    fn play(&mut self, freq: u32) -> Result<(), M5GoError> {
+        self.stop()?;
+
+        let config = TimerConfig::new().frequency(Hertz(freq));
+        // Safe because the previous driver, the only other user of these peripherals, was dropped
+        let timer_driver =
+            LedcTimerDriver::new(unsafe { self.timer.clone_unchecked() }, &config)
+                .map_err(|e| M5GoError::speaker(&format!("Unable to play {freq} Hz"), e))?;
+        let mut tone = LedcDriver::new(
+            unsafe { self.channel.clone_unchecked() },
+            timer_driver,
+            unsafe { self.pin.clone_unchecked() },
+        )
+        .map_err(|e| M5GoError::speaker("Unable to drive the speaker pin", e))?;
+        tone.set_duty(1)
+            .map_err(|e| M5GoError::speaker("Unable to set duty", e))?;
+
+        self.tone = Some((freq, tone));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), M5GoError> {
+        if let Some((_, mut tone)) = self.tone.take() {
+            tone.disable()
+                .map_err(|e| M5GoError::speaker("Error stopping sound", e))?;
+        }
+        Ok(())
+    }
+
+    fn playing(&self) -> Option<u32> {
+        self.tone.as_ref().map(|(freq, _)| *freq)
+    }
+}
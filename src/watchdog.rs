@@ -0,0 +1,257 @@
+//! Deadlines for the main loop and background tasks, naming the task that hung.
+//!
+//! Tasks register a handle with its own timeout and feed it. A supervisor thread checks the
+//! deadlines and panics with the name of the late task, so that the panic hook shows it on the
+//! screen and the crash report records it before the reset (see
+//! [`M5Go::with_panic_hook`](crate::M5Go)). On ESP the supervisor is itself registered with the
+//! ESP-IDF task watchdog, which resets the chip if the supervisor stalls too.
+
+use std::{
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{hal::Platform, M5Go, M5GoError};
+
+/// The supervisor panics to report a late task, the panic hook then runs on its stack: it writes
+/// the crash report to the NVS and draws the panic screen
+const SUPERVISOR_STACK_SIZE: usize = 16 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WatchdogConfig {
+    /// How often the deadlines are checked
+    pub check_interval: Duration,
+    /// Timeout of the ESP-IDF task watchdog watching the supervisor, in seconds
+    pub hardware_timeout: u32,
+}
+
+impl WatchdogConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    pub fn hardware_timeout(mut self, seconds: u32) -> Self {
+        self.hardware_timeout = seconds;
+        self
+    }
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_millis(100),
+            hardware_timeout: 10,
+        }
+    }
+}
+
+struct Entry {
+    id: u64,
+    name: String,
+    timeout: Duration,
+    last_feed: Instant,
+    /// Only armed handles are checked
+    armed: bool,
+}
+
+#[derive(Default)]
+struct Entries {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+/// Cloning the watchdog gives another handle on the same supervisor, to register other tasks
+#[derive(Clone)]
+pub struct Watchdog {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl Watchdog {
+    /// Start the supervisor, it stops once the watchdog and all its handles are dropped
+    pub fn start(config: WatchdogConfig) -> Result<Self, M5GoError> {
+        let entries = Arc::new(Mutex::new(Entries::default()));
+        let supervised = Arc::downgrade(&entries);
+
+        hardware::init(config.hardware_timeout)?;
+
+        thread::Builder::new()
+            .name("watchdog".to_string())
+            .stack_size(SUPERVISOR_STACK_SIZE)
+            .spawn(move || supervise(supervised, config.check_interval))
+            .map_err(|e| M5GoError::watchdog("Unable to start the supervisor", e))?;
+
+        Ok(Self { entries })
+    }
+
+    /// A handle armed right away, it must be fed at least every `timeout`
+    pub fn register(&self, name: &str, timeout: Duration) -> WatchdogHandle {
+        let handle = self.register_disarmed(name, timeout);
+        handle.feed();
+        handle
+    }
+
+    /// A handle only checked while [`WatchdogHandle::watch`] guards some work, for callbacks
+    /// that run now and then
+    pub fn register_disarmed(&self, name: &str, timeout: Duration) -> WatchdogHandle {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let id = entries.next_id;
+        entries.next_id += 1;
+        entries.entries.push(Entry {
+            id,
+            name: name.to_string(),
+            timeout,
+            last_feed: Instant::now(),
+            armed: false,
+        });
+        WatchdogHandle {
+            id,
+            entries: Arc::clone(&self.entries),
+        }
+    }
+}
+
+/// Dropping the handle unregisters it
+pub struct WatchdogHandle {
+    id: u64,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl WatchdogHandle {
+    /// Restart the deadline, arming the handle if it was not
+    pub fn feed(&self) {
+        self.update(|entry| {
+            entry.last_feed = Instant::now();
+            entry.armed = true;
+        });
+    }
+
+    /// Stop checking the deadline until the next feed, before a long wait for example
+    pub fn disarm(&self) {
+        self.update(|entry| entry.armed = false);
+    }
+
+    /// The work until the guard is dropped must not take longer than the timeout
+    pub fn watch(&self) -> WatchGuard<'_> {
+        self.feed();
+        WatchGuard { handle: self }
+    }
+
+    fn update<F: FnOnce(&mut Entry)>(&self, f: F) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(entry) = entries.entries.iter_mut().find(|entry| entry.id == self.id) {
+                f(entry);
+            }
+        }
+    }
+}
+
+impl Drop for WatchdogHandle {
+    fn drop(&mut self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.entries.retain(|entry| entry.id != self.id);
+        }
+    }
+}
+
+pub struct WatchGuard<'a> {
+    handle: &'a WatchdogHandle,
+}
+
+impl Drop for WatchGuard<'_> {
+    fn drop(&mut self) {
+        self.handle.disarm();
+    }
+}
+
+fn supervise(entries: Weak<Mutex<Entries>>, check_interval: Duration) {
+    hardware::add_current_task();
+
+    loop {
+        thread::sleep(check_interval);
+        hardware::feed();
+
+        let Some(entries) = entries.upgrade() else {
+            break;
+        };
+        // A task holding the lock forever hangs the supervisor, the hardware watchdog then
+        // resets the chip
+        let late = entries.lock().ok().and_then(|entries| {
+            entries
+                .entries
+                .iter()
+                .find(|entry| entry.armed && entry.last_feed.elapsed() > entry.timeout)
+                .map(|entry| (entry.name.clone(), entry.timeout))
+        });
+
+        if let Some((name, timeout)) = late {
+            panic!(
+                "Watchdog: '{}' missed its {} ms deadline",
+                name,
+                timeout.as_millis()
+            );
+        }
+    }
+
+    hardware::delete_current_task();
+}
+
+impl<P: Platform> M5Go<P> {
+    /// Start the watchdog, [`M5Go::run`] and BLE set up afterwards are then supervised
+    pub fn start_watchdog(&mut self, config: WatchdogConfig) -> Result<Watchdog, M5GoError> {
+        let watchdog = Watchdog::start(config)?;
+        self.watchdog = Some(watchdog.clone());
+        Ok(watchdog)
+    }
+}
+
+#[cfg(feature = "esp")]
+mod hardware {
+    use esp_idf_sys::*;
+
+    use crate::M5GoError;
+
+    pub fn init(timeout: u32) -> Result<(), M5GoError> {
+        // Also updates the timeout when ESP-IDF already started the task watchdog
+        esp!(unsafe { esp_task_wdt_init(timeout, true) })
+            .map_err(|e| M5GoError::watchdog("Unable to set up the task watchdog", e))
+    }
+
+    pub fn add_current_task() {
+        if let Err(e) = esp!(unsafe { esp_task_wdt_add(std::ptr::null_mut()) }) {
+            log::warn!("Unable to register the supervisor: {:?}", e);
+        }
+    }
+
+    pub fn feed() {
+        unsafe { esp_task_wdt_reset() };
+    }
+
+    pub fn delete_current_task() {
+        unsafe { esp_task_wdt_delete(std::ptr::null_mut()) };
+    }
+}
+
+/// Nothing resets the host when the supervisor stalls
+#[cfg(not(feature = "esp"))]
+mod hardware {
+    use crate::M5GoError;
+
+    pub fn init(_timeout: u32) -> Result<(), M5GoError> {
+        Ok(())
+    }
+
+    pub fn add_current_task() {}
+
+    pub fn feed() {}
+
+    pub fn delete_current_task() {}
+}